@@ -3,203 +3,2109 @@
 
 //! The actual download code
 
+use crate::decompress::{Decompression, VerifyTarget};
+use crate::download::{ExistingFile, FileAction, Naming, PartialFile, Sink};
+use crate::event::Event;
+use crate::mirror::Selector;
+use crate::verify::Streaming;
 use crate::{Download, DownloadSummary, Error, Result, Verification};
 
-use futures::stream::{self, StreamExt};
-use rand::seq::SliceRandom;
+use futures::stream::StreamExt;
 
+use std::convert::TryFrom;
 use std::io::{Seek, SeekFrom, Write};
 
-fn select_url(urls: &[String]) -> String {
-    assert!(!urls.is_empty());
-    urls.choose(&mut rand::thread_rng()).unwrap().clone()
+/// Limits the number of HTTP connections open at the same time, in total and per host.
+pub(crate) struct Connections {
+    parallel_requests: u16,
+    total: tokio::sync::Semaphore,
+    /// The limit for hosts not found in `host_limits`, `None` for no limit.
+    per_host: Option<u16>,
+    host_limits: std::collections::HashMap<String, u16>,
+    hosts:
+        std::sync::Mutex<std::collections::HashMap<String, std::sync::Arc<tokio::sync::Semaphore>>>,
+}
+
+/// Permission to open a connection.
+struct Connection<'a> {
+    _host: Option<tokio::sync::OwnedSemaphorePermit>,
+    _total: tokio::sync::SemaphorePermit<'a>,
+}
+
+impl Connections {
+    pub(crate) fn new(
+        parallel_requests: u16,
+        per_host: Option<u16>,
+        host_limits: std::collections::HashMap<String, u16>,
+    ) -> Self {
+        Self {
+            parallel_requests,
+            total: tokio::sync::Semaphore::new(usize::from(parallel_requests.max(1))),
+            per_host,
+            host_limits,
+            hosts: std::sync::Mutex::default(),
+        }
+    }
+
+    /// The maximum number of connections to `host`, `None` if there is no limit.
+    fn limit(&self, host: &str) -> Option<u16> {
+        self.host_limits
+            .get(host)
+            .copied()
+            .or(self.per_host)
+            .map(|l| l.max(1))
+    }
+
+    /// Wait until a connection to `url` may be opened.
+    ///
+    /// The host is waited for first, so that downloads waiting for a busy host
+    /// do not block connections to other hosts.
+    async fn acquire(&self, url: &str) -> Option<Connection<'_>> {
+        let host = crate::mirror::host(url);
+        let semaphore = self.limit(&host).map(|limit| {
+            self.hosts
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .entry(host)
+                .or_insert_with(|| {
+                    std::sync::Arc::new(tokio::sync::Semaphore::new(usize::from(limit)))
+                })
+                .clone()
+        });
+        let host = match semaphore {
+            Some(s) => Some(s.acquire_owned().await.ok()?),
+            None => None,
+        };
+        Some(Connection {
+            _host: host,
+            _total: self.total.acquire().await.ok()?,
+        })
+    }
+}
+
+/// Everything shared between the downloads of one run.
+pub(crate) struct Context {
+    pub(crate) client: reqwest::Client,
+    pub(crate) retries: u16,
+    /// The retry policy used for `Download`s without their own.
+    pub(crate) retry_policy: crate::retry::Policy,
+    pub(crate) connections: Connections,
+    /// Health of all hosts, shared with other runs of the same `Downloader`.
+    pub(crate) health: std::sync::Arc<crate::mirror::Health>,
+    /// Cancels all downloads of the run.
+    pub(crate) cancel: crate::cancel::Token,
+    /// Limits the bandwidth of all downloads of the run together.
+    pub(crate) bandwidth: crate::bandwidth::Limiter,
+    /// Receive the events of all downloads of the run.
+    pub(crate) events: crate::event::Subscribers,
+    /// Set the modification time of `Download`s without their own setting
+    /// from `Last-Modified`.
+    pub(crate) preserve_mtime: bool,
+}
+
+impl Context {
+    /// Update the health of the host of `url` with the outcome of an `attempt`
+    /// that took `elapsed` and received `bytes`.
+    fn record(&self, url: &str, attempt: &Attempt, bytes: u64, elapsed: std::time::Duration) {
+        if attempt.is_complete() {
+            self.health.success(url, attempt.latency, bytes, elapsed);
+        } else if attempt.is_host_failure() {
+            self.health.failure(url);
+        }
+    }
+
+    /// Account for `bytes` received by a download with the bandwidth `limit`,
+    /// waiting until both that and the limit of the run allow for more.
+    async fn throttle(&self, limit: Option<&crate::bandwidth::Limiter>, bytes: u64) {
+        self.bandwidth.consume(bytes).await;
+        if let Some(limit) = limit {
+            limit.consume(bytes).await;
+        }
+    }
+
+    /// Has the run or the download with the `token` been cancelled?
+    fn is_cancelled(&self, token: Option<&crate::cancel::Token>) -> bool {
+        self.cancel.is_cancelled() || token.is_some_and(crate::cancel::Token::is_cancelled)
+    }
+
+    /// Drive `future` to completion, unless the run or the download with the
+    /// `token` gets cancelled first. `future` is dropped in that case, which
+    /// aborts all requests it has in flight.
+    async fn until_cancelled<F: std::future::Future>(
+        &self,
+        token: Option<&crate::cancel::Token>,
+        future: F,
+    ) -> Option<F::Output> {
+        let cancelled = async {
+            match token {
+                Some(token) => {
+                    futures::future::select(
+                        Box::pin(self.cancel.cancelled()),
+                        Box::pin(token.cancelled()),
+                    )
+                    .await;
+                }
+                None => self.cancel.cancelled().await,
+            }
+        };
+        futures::pin_mut!(future, cancelled);
+        match futures::future::select(future, cancelled).await {
+            futures::future::Either::Left((output, _)) => Some(output),
+            futures::future::Either::Right(_) => None,
+        }
+    }
+}
+
+/// Where to report on the progress of one download.
+struct Report {
+    progress: crate::Progress,
+    events: crate::event::Emitter,
+    /// The last successful response.
+    response: std::sync::Mutex<Option<crate::metadata::Response>>,
+}
+
+impl Report {
+    fn new(progress: crate::Progress, events: crate::event::Emitter) -> Self {
+        Self {
+            progress,
+            events,
+            response: std::sync::Mutex::default(),
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        self.events.emit(event);
+    }
+
+    /// Report the `response` to a request to `url`.
+    fn connected(&self, url: &str, response: &reqwest::Response) {
+        if response.status().is_success() {
+            let content_length = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                content_range(response).and_then(|(_, total)| total)
+            } else {
+                response.content_length()
+            };
+            *self
+                .response
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner) =
+                Some(crate::metadata::Response {
+                    url: response.url().to_string(),
+                    status: response.status().as_u16(),
+                    headers: response.headers().clone(),
+                    content_type: header_value(response, reqwest::header::CONTENT_TYPE),
+                    content_length,
+                });
+        }
+        self.emit(Event::Connected {
+            url: url.to_owned(),
+            status: response.status().as_u16(),
+            headers: response.headers().clone(),
+            content_length: response.content_length(),
+        });
+    }
+
+    fn response(&self) -> Option<crate::metadata::Response> {
+        self.response
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// State kept between attempts to download one file, so that an interrupted
+/// transfer can be continued with a HTTP `Range` request.
+#[derive(Default)]
+struct ResumeState {
+    /// Number of bytes of the file that are already written to disk.
+    offset: u64,
+    /// The `ETag` or `Last-Modified` value of the response the existing bytes
+    /// came from. Sent as `If-Range` when continuing.
+    validator: Option<String>,
+    /// The file downloaded into, if any. The `validator` is stored next to it,
+    /// so that later runs can continue the download.
+    path: Option<std::path::PathBuf>,
+    /// Bytes of an earlier run that did not need to get downloaded again
+    /// thanks to a `Range` request.
+    resumed: u64,
+    /// Bytes received from the network.
+    fetched: u64,
+    /// Set if the server answered a conditional request with `304 Not Modified`.
+    unchanged: bool,
+    /// Creates the `verifier`, `None` if the download is not verified while
+    /// it is received.
+    streaming: Option<crate::StreamingVerify>,
+    /// Verifies the bytes written to disk so far.
+    verifier: Option<Box<dyn Streaming>>,
+    /// When to decompress the data while it is received and which side of the
+    /// decoder to verify, `None` to keep the data as it is.
+    decompress: Option<(Decompression, VerifyTarget)>,
+    /// Decompresses the data of the current response, if it needs decompressing.
+    decoder: Option<crate::decompress::Decoder>,
+}
+
+impl ResumeState {
+    /// A `Range` request continuing at `offset` was accepted.
+    ///
+    /// Only the bytes on disk before anything was fetched count as resumed:
+    /// Bytes fetched by an earlier attempt of this run are in `fetched` already.
+    const fn resume(&mut self) {
+        if self.fetched == 0 {
+            self.resumed = self.offset;
+        }
+    }
+}
+
+/// How to verify a download while it is received, with the verifier fed
+/// with the complete file (if any).
+type Streamed = (crate::StreamingVerify, Option<Box<dyn Streaming>>);
+
+/// Where the data of a download goes while it is received.
+enum Output {
+    /// The `.part` file, which is handed to the sink once the download is complete.
+    File(std::io::BufWriter<std::fs::File>),
+    /// The data of a `Sink::Memory` download.
+    Memory(Vec<u8>),
+    /// The writer of a `Sink::Writer` download, with the number of bytes
+    /// written into it so far.
+    Writer(crate::download::Writer, u64),
+}
+
+impl Output {
+    async fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::File(file) => file.write_all(bytes),
+            Self::Memory(data) => {
+                data.extend_from_slice(bytes);
+                Ok(())
+            }
+            Self::Writer(writer, written) => {
+                use tokio::io::AsyncWriteExt;
+
+                writer.lock().await.write_all(bytes).await?;
+                *written += bytes.len() as u64;
+                Ok(())
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Memory(_) => Ok(()),
+            Self::Writer(writer, _) => {
+                use tokio::io::AsyncWriteExt;
+
+                writer.lock().await.flush().await
+            }
+        }
+    }
+
+    /// Throw away all data written so far.
+    fn clear(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::File(file) => {
+                file.flush()?;
+                file.get_ref().set_len(0)?;
+                file.seek(SeekFrom::Start(0)).map(|_| ())
+            }
+            Self::Memory(data) => {
+                data.clear();
+                Ok(())
+            }
+            Self::Writer(_, 0) => Ok(()),
+            Self::Writer(..) => Err(std::io::Error::other(
+                "Can not start over: Data was written into the writer already.",
+            )),
+        }
+    }
+
+    fn into_received(self) -> Received {
+        match self {
+            Self::File(_) => Received::Spooled,
+            Self::Memory(data) => Received::Memory(data),
+            Self::Writer(..) => Received::Written,
+        }
+    }
+}
+
+/// Where the data of a complete download ended up.
+enum Received {
+    /// In the `.part` file.
+    Spooled,
+    /// In memory, for `Sink::Memory`.
+    Memory(Vec<u8>),
+    /// In the writer of `Sink::Writer`.
+    Written,
+}
+
+/// The outcome of `fetch`.
+struct Fetched {
+    /// The last progress message.
+    message: String,
+    /// The streaming verifier, if it was fed the complete data while downloading.
+    verifier: Option<Box<dyn Streaming>>,
+    received: Received,
+    /// Was the data decompressed while it was received?
+    decompressed: bool,
+}
+
+/// Why an attempt did not produce the complete file (or segment).
+enum Failure {
+    /// The server answered with a status code that does not provide the file.
+    Status,
+    /// Reading from or writing to the local file system failed.
+    Io(std::io::Error),
+    /// Sending the request or receiving the body failed.
+    Transport(reqwest::Error),
+    /// The body ended before all the bytes announced in `Content-Length` arrived.
+    Truncated { expected: u64, received: u64 },
+    /// The server did not send the range of a segment that was asked for, so
+    /// the file needs to get downloaded over one connection instead.
+    Unranged,
+    /// The data could not be decompressed while it was received.
+    Decompression(std::io::Error),
+}
+
+impl Failure {
+    fn into_error(self, summary: DownloadSummary) -> Error {
+        match self {
+            Self::Status | Self::Unranged => Error::Download(summary),
+            Self::Io(e) => Error::File(summary, e),
+            Self::Transport(e) if e.is_timeout() => Error::Timeout(summary, e),
+            Self::Transport(e) if e.is_connect() => Error::Connection(summary, e),
+            Self::Transport(e) => Error::Transfer(summary, e),
+            Self::Truncated { expected, received } => Error::Truncated(summary, received, expected),
+            Self::Decompression(e) => Error::Decompression(summary, e),
+        }
+    }
+}
+
+/// The outcome of one request.
+struct Attempt {
+    /// The HTTP status code, `None` if no response was received at all.
+    status: Option<u16>,
+    /// Why the file (or segment) was not received completely, `None` if it was.
+    failure: Option<Failure>,
+    /// How long the server asked to wait before trying again.
+    retry_after: Option<std::time::Duration>,
+    /// How long it took for the response headers to arrive.
+    latency: Option<std::time::Duration>,
+}
+
+impl Attempt {
+    const fn new(status: Option<u16>, failure: Option<Failure>) -> Self {
+        Self {
+            status,
+            failure,
+            retry_after: None,
+            latency: None,
+        }
+    }
+
+    const fn failed(status: Option<u16>) -> Self {
+        Self::new(status, Some(Failure::Status))
+    }
+
+    const fn is_complete(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// Does this attempt say anything bad about the host it went to?
+    fn is_host_failure(&self) -> bool {
+        match &self.failure {
+            None | Some(Failure::Io(_) | Failure::Unranged | Failure::Decompression(_)) => false,
+            Some(Failure::Transport(_) | Failure::Truncated { .. }) => true,
+            Some(Failure::Status) => self.status.is_none_or(|s| s >= 500),
+        }
+    }
+
+    /// Should this attempt be retried according to `policy`?
+    fn is_retryable(&self, policy: &crate::retry::Policy) -> bool {
+        match (&self.failure, self.status) {
+            (None | Some(Failure::Io(_) | Failure::Unranged | Failure::Decompression(_)), _) => {
+                false
+            }
+            (Some(Failure::Transport(_) | Failure::Truncated { .. }), _) => {
+                policy.is_retryable(None)
+            }
+            // Ranges that had to be restarted:
+            (Some(Failure::Status), Some(s)) if (200..300).contains(&s) || s == 416 => {
+                policy.is_retryable(None)
+            }
+            (Some(Failure::Status), s) => {
+                policy.is_retryable(s) && !policy.exceeds_max_delay(self.retry_after)
+            }
+        }
+    }
+
+    /// Could another mirror succeed where this attempt failed?
+    const fn is_mirror_failure(&self) -> bool {
+        !matches!(
+            self.failure,
+            Some(Failure::Io(_) | Failure::Unranged | Failure::Decompression(_))
+        )
+    }
+
+    /// A description of why this attempt failed.
+    fn reason(&self) -> String {
+        match (&self.failure, self.status) {
+            (None, _) => String::from("complete"),
+            (Some(Failure::Status), Some(s)) => format!("status {s}"),
+            (Some(Failure::Status), None) => String::from("no response"),
+            (Some(Failure::Io(e)), _) => e.to_string(),
+            (Some(Failure::Transport(e)), _) => e.to_string(),
+            (Some(Failure::Truncated { expected, received }), _) => {
+                format!("truncated after {received} of {expected} bytes")
+            }
+            (Some(Failure::Unranged), _) => String::from("the requested range was not sent"),
+            (Some(Failure::Decompression(e)), _) => format!("decompressing failed: {e}"),
+        }
+    }
+
+    /// Move the failure out of this attempt.
+    fn take_failure(&mut self) -> Failure {
+        self.failure.take().unwrap_or(Failure::Status)
+    }
+}
+
+fn retry_after(response: &reqwest::Response) -> Option<std::time::Duration> {
+    let status = response.status();
+    if status != reqwest::StatusCode::TOO_MANY_REQUESTS
+        && status != reqwest::StatusCode::SERVICE_UNAVAILABLE
+    {
+        return None;
+    }
+    crate::retry::parse_retry_after(&header_value(response, reqwest::header::RETRY_AFTER)?)
+}
+
+fn header_value(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(std::borrow::ToOwned::to_owned)
+}
+
+/// Parse a `Content-Range` header of the form `bytes <start>-<end>/<total>`
+/// or `bytes */<total>` into its start offset (if any) and total length (if known).
+fn content_range(response: &reqwest::Response) -> Option<(Option<u64>, Option<u64>)> {
+    parse_content_range(&header_value(response, reqwest::header::CONTENT_RANGE)?)
+}
+
+fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let total = total.trim().parse::<u64>().ok();
+    let start = if range.trim() == "*" {
+        None
+    } else {
+        Some(range.split_once('-')?.0.trim().parse::<u64>().ok()?)
+    };
+    Some((start, total))
+}
+
+fn restart(output: &mut Output, state: &mut ResumeState) -> std::io::Result<()> {
+    output.clear()?;
+    state.offset = 0;
+    state.resumed = 0;
+    state.validator = None;
+    state.verifier = state.streaming.as_ref().map(|s| s());
+    state.decoder = None;
+    Ok(())
+}
+
+/// Feed `data` to the streaming `verifier`, failing if it panics.
+fn update_verifier(verifier: &mut dyn Streaming, data: &[u8]) -> std::result::Result<(), Failure> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| verifier.update(data)))
+        .map_err(|_| Failure::Io(std::io::Error::other("The verifier panicked")))
+}
+
+/// Hand the `bytes` received to the `output`, decompressing them first if
+/// requested. The verifier gets to see the side of the decoder it is meant to.
+async fn consume(
+    output: &mut Output,
+    state: &mut ResumeState,
+    bytes: &[u8],
+) -> std::result::Result<(), Failure> {
+    let Some(decoder) = &mut state.decoder else {
+        if let Some(verifier) = &mut state.verifier {
+            update_verifier(&mut **verifier, bytes)?;
+        }
+        return output.write(bytes).await.map_err(Failure::Io);
+    };
+    let verify_decoded = matches!(state.decompress, Some((_, VerifyTarget::Decompressed)));
+    if let Some(verifier) = state.verifier.as_mut().filter(|_| !verify_decoded) {
+        update_verifier(&mut **verifier, bytes)?;
+    }
+    let data = decoder.update(bytes).map_err(Failure::Decompression)?;
+    if let Some(verifier) = state.verifier.as_mut().filter(|_| verify_decoded) {
+        update_verifier(&mut **verifier, &data)?;
+    }
+    output.write(&data).await.map_err(Failure::Io)
+}
+
+/// Hand the rest of the decompressed data to the `output` once all data was
+/// received. Fails if the data ended early.
+async fn finish_decoding(
+    output: &mut Output,
+    state: &mut ResumeState,
+) -> std::result::Result<(), Failure> {
+    let Some(decoder) = &mut state.decoder else {
+        return Ok(());
+    };
+    let data = decoder.finish().map_err(Failure::Decompression)?;
+    let verify_decoded = matches!(state.decompress, Some((_, VerifyTarget::Decompressed)));
+    if let Some(verifier) = state.verifier.as_mut().filter(|_| verify_decoded) {
+        update_verifier(&mut **verifier, &data)?;
+    }
+    output.write(&data).await.map_err(Failure::Io)
+}
+
+/// Build a request to `url` with the method, headers, authentication and
+/// body of `download`.
+fn request(
+    ctx: &Context,
+    download: &Download,
+    method: reqwest::Method,
+    url: &str,
+) -> reqwest::RequestBuilder {
+    let mut request = ctx.client.request(method, url);
+    request = match &download.auth {
+        Some(crate::download::Auth::Basic { user, password }) => {
+            request.basic_auth(user, password.as_ref())
+        }
+        Some(crate::download::Auth::Bearer(token)) => request.bearer_auth(token),
+        None => request,
+    };
+    request = request.headers(download.headers.clone());
+    if let Some(headers) = download.mirror_headers.get(url) {
+        request = request.headers(headers.clone());
+    }
+    if let Some(body) = &download.body {
+        request = request.body(body.clone());
+    }
+    request
 }
 
 async fn download_url(
-    client: reqwest::Client,
-    url: String,
-    writer: &mut std::io::BufWriter<std::fs::File>,
-    progress: &mut crate::Progress,
+    ctx: &Context,
+    url: &str,
+    download: &Download,
+    output: &mut Output,
+    state: &mut ResumeState,
+    report: &Report,
     message: &str,
-) -> u16 {
-    if let Ok(mut response) = client.get(&url).send().await {
-        let total = response.content_length();
-        let mut current: u64 = 0;
-        writer.seek(SeekFrom::Start(current)).unwrap_or(0);
+) -> Attempt {
+    let mut request = request(ctx, download, download.method.clone(), url);
+    if state.offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", state.offset));
+        if let Some(v) = &state.validator {
+            request = request.header(reqwest::header::IF_RANGE, v);
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let response = match request.send().await {
+        Ok(r) => r,
+        Err(e) => return Attempt::new(None, Some(Failure::Transport(e))),
+    };
+    let latency = start.elapsed();
+    report.connected(url, &response);
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED
+        && (download
+            .headers
+            .contains_key(reqwest::header::IF_NONE_MATCH)
+            || download
+                .headers
+                .contains_key(reqwest::header::IF_MODIFIED_SINCE))
+    {
+        report
+            .progress
+            .set_message(&format!("{message} - {}", response.status().as_u16()));
+        state.unchanged = true;
+        return Attempt {
+            latency: Some(latency),
+            ..Attempt::new(Some(response.status().as_u16()), None)
+        };
+    }
 
-        progress.setup(total, message);
+    Attempt {
+        latency: Some(latency),
+        ..receive(
+            ctx,
+            download.bandwidth.as_ref(),
+            response,
+            output,
+            state,
+            report,
+            message,
+        )
+        .await
+    }
+}
 
-        while let Some(bytes) = response.chunk().await.unwrap_or(None) {
-            if writer.write_all(&bytes).is_err() {}
+/// Write the body of `response` into `output`, continuing the data received
+/// before if possible.
+async fn receive(
+    ctx: &Context,
+    limit: Option<&crate::bandwidth::Limiter>,
+    mut response: reqwest::Response,
+    output: &mut Output,
+    state: &mut ResumeState,
+    report: &Report,
+    message: &str,
+) -> Attempt {
+    let status = response.status();
+    let code = Some(status.as_u16());
 
-            current += bytes.len() as u64;
-            progress.progress(current);
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && state.offset > 0 {
+        report
+            .progress
+            .set_message(&format!("{message} - {}", status.as_u16()));
+        // The file is complete already if the server reports exactly the size we have:
+        if content_range(&response) == Some((None, Some(state.offset))) {
+            state.resume();
+            return Attempt::new(code, None);
+        }
+        if let Err(e) = restart(output, state) {
+            return Attempt::new(code, Some(Failure::Io(e)));
         }
+        return Attempt::failed(code);
+    }
 
-        let result = response.status().as_u16();
-        progress.set_message(&format!("{message} - {result}"));
-        result
+    if !status.is_success() {
+        report
+            .progress
+            .set_message(&format!("{message} - {}", status.as_u16()));
+        return Attempt {
+            retry_after: retry_after(&response),
+            ..Attempt::failed(code)
+        };
+    }
+
+    if status == reqwest::StatusCode::PARTIAL_CONTENT && state.offset > 0 {
+        if !matches!(content_range(&response), Some((Some(start), _)) if start == state.offset) {
+            // The server sent some other range than requested: Start over next time.
+            if let Err(e) = restart(output, state) {
+                return Attempt::new(code, Some(Failure::Io(e)));
+            }
+            return Attempt::failed(code);
+        }
+        state.resume();
     } else {
-        reqwest::StatusCode::BAD_REQUEST.as_u16()
+        // The server ignored the range (or the file changed): Fetch everything again.
+        if state.offset > 0 {
+            if let Err(e) = restart(output, state) {
+                return Attempt::new(code, Some(Failure::Io(e)));
+            }
+        }
+        let metadata = crate::metadata::Metadata::from_headers(response.headers());
+        state.validator = metadata.validator();
+        if let Some(Err(e)) = state.path.as_ref().map(|p| metadata.store(p)) {
+            return Attempt::new(code, Some(Failure::Io(e)));
+        }
+        let format = state.decompress.and_then(|(decompress, _)| {
+            compression(
+                decompress,
+                header_value(&response, reqwest::header::CONTENT_ENCODING).as_deref(),
+            )
+        });
+        state.decoder = match format.map(crate::decompress::Decoder::new).transpose() {
+            Ok(decoder) => decoder,
+            Err(e) => return Attempt::new(code, Some(Failure::Decompression(e))),
+        };
+    }
+
+    let length = response.content_length();
+
+    report
+        .progress
+        .setup(length.map(|l| l + state.offset), message);
+    report.progress.progress(state.offset);
+
+    let mut received = 0;
+    let failure = loop {
+        match response.chunk().await {
+            Ok(Some(bytes)) => {
+                if let Err(failure) = consume(output, state, &bytes).await {
+                    break Some(failure);
+                }
+                received += bytes.len() as u64;
+                state.offset += bytes.len() as u64;
+                state.fetched += bytes.len() as u64;
+                report.progress.progress(state.offset);
+                report.emit(Event::Received {
+                    bytes: bytes.len() as u64,
+                    total: state.offset,
+                });
+                ctx.throttle(limit, bytes.len() as u64).await;
+            }
+            Ok(None) => {
+                break length
+                    .filter(|expected| received < *expected)
+                    .map(|expected| Failure::Truncated { expected, received })
+            }
+            Err(e) => break Some(Failure::Transport(e)),
+        }
+    };
+
+    report
+        .progress
+        .set_message(&format!("{message} - {}", status.as_u16()));
+    Attempt::new(code, failure)
+}
+
+// ----------------------------------------------------------------------
+// - Segmented downloads:
+// ----------------------------------------------------------------------
+
+/// Segments smaller than this are not worth an extra connection.
+const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+
+/// What a `HEAD` request revealed about the file to download in segments.
+struct Probe {
+    length: u64,
+    /// Sent as `If-Range` with each segment, so that all segments come from
+    /// the same version of the file.
+    validator: Option<String>,
+}
+
+/// Find the length of the file at `url`, if the server supports `Range` requests.
+async fn probe(ctx: &Context, download: &Download, url: &str) -> Option<Probe> {
+    let _connection = ctx.connections.acquire(url).await?;
+    let response = request(ctx, download, reqwest::Method::HEAD, url)
+        .send()
+        .await
+        .ok()?;
+    if !response.status().is_success()
+        || header_value(&response, reqwest::header::ACCEPT_RANGES).as_deref() != Some("bytes")
+    {
+        return None;
+    }
+    Some(Probe {
+        length: header_value(&response, reqwest::header::CONTENT_LENGTH)?
+            .parse()
+            .ok()?,
+        validator: crate::metadata::Metadata::from_headers(response.headers()).validator(),
+    })
+}
+
+/// Split `length` bytes into up to `count` ranges of the form `(start, end)`
+/// with `end` being exclusive.
+fn split_ranges(length: u64, count: u16) -> Vec<(u64, u64)> {
+    let count = u64::from(count).min(length / MIN_SEGMENT_SIZE).max(1);
+    let size = length.div_ceil(count);
+    (0..count)
+        .map(|i| (i * size, ((i + 1) * size).min(length)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+/// The outcome of downloading one segment of a file.
+struct Segment {
+    status: Vec<(String, Option<u16>)>,
+    fetched: u64,
+    failure: Option<Failure>,
+}
+
+/// Everything shared between the segments of one download.
+struct Segments<'a> {
+    ctx: &'a Context,
+    download: &'a Download,
+    urls: &'a [String],
+    policy: &'a crate::retry::Policy,
+    path: &'a std::path::Path,
+    probe: &'a Probe,
+    received: std::sync::atomic::AtomicU64,
+    report: &'a Report,
+}
+
+async fn fetch_range(
+    segments: &Segments<'_>,
+    url: &str,
+    (start, end): (u64, u64),
+    done: &mut u64,
+) -> Attempt {
+    let Some(_connection) = segments.ctx.connections.acquire(url).await else {
+        return Attempt::failed(None);
+    };
+    let begin = std::time::Instant::now();
+    let mut request = request(segments.ctx, segments.download, reqwest::Method::GET, url).header(
+        reqwest::header::RANGE,
+        format!("bytes={}-{}", start + *done, end - 1),
+    );
+    if let Some(v) = &segments.probe.validator {
+        request = request.header(reqwest::header::IF_RANGE, v);
+    }
+    let response = match request.send().await {
+        Ok(r) => r,
+        Err(e) => return Attempt::new(None, Some(Failure::Transport(e))),
+    };
+    let latency = begin.elapsed();
+    segments.report.connected(url, &response);
+
+    Attempt {
+        latency: Some(latency),
+        ..receive_range(segments, response, (start, end), done).await
+    }
+}
+
+/// Write the body of `response` into the segment `start..end` of the file.
+async fn receive_range(
+    segments: &Segments<'_>,
+    mut response: reqwest::Response,
+    (start, end): (u64, u64),
+    done: &mut u64,
+) -> Attempt {
+    let status = response.status();
+    let code = Some(status.as_u16());
+    if !status.is_success() {
+        return Attempt {
+            retry_after: retry_after(&response),
+            ..Attempt::failed(code)
+        };
+    }
+    // The server ignored the range, or the file changed since the probe:
+    // The segments can not be put together.
+    let length = segments.probe.length;
+    if status != reqwest::StatusCode::PARTIAL_CONTENT
+        || !matches!(content_range(&response), Some((Some(s), total))
+            if s == start + *done && total.is_none_or(|t| t == length))
+    {
+        return Attempt::new(code, Some(Failure::Unranged));
+    }
+
+    let file = match std::fs::OpenOptions::new().write(true).open(segments.path) {
+        Ok(f) => f,
+        Err(e) => return Attempt::new(code, Some(Failure::Io(e))),
+    };
+    let mut writer = std::io::BufWriter::new(file);
+    if let Err(e) = writer.seek(SeekFrom::Start(start + *done)) {
+        return Attempt::new(code, Some(Failure::Io(e)));
+    }
+
+    let failure = loop {
+        if start + *done == end {
+            break writer.flush().err().map(Failure::Io);
+        }
+        match response.chunk().await {
+            Ok(Some(bytes)) => {
+                // Never write past the end of the segment, even if the server sends more:
+                let len = bytes
+                    .len()
+                    .min(usize::try_from(end - start - *done).unwrap_or(usize::MAX));
+                if let Err(e) = writer.write_all(&bytes[..len]) {
+                    break Some(Failure::Io(e));
+                }
+                *done += len as u64;
+                let current = segments
+                    .received
+                    .fetch_add(len as u64, std::sync::atomic::Ordering::Relaxed);
+                segments.report.progress.progress(current + len as u64);
+                segments.report.emit(Event::Received {
+                    bytes: len as u64,
+                    total: current + len as u64,
+                });
+                segments
+                    .ctx
+                    .throttle(segments.download.bandwidth.as_ref(), len as u64)
+                    .await;
+            }
+            Ok(None) => {
+                break Some(Failure::Truncated {
+                    expected: end - start,
+                    received: *done,
+                })
+            }
+            Err(e) => break Some(Failure::Transport(e)),
+        }
+    };
+
+    Attempt::new(code, failure)
+}
+
+async fn download_segment(segments: &Segments<'_>, index: usize, range: (u64, u64)) -> Segment {
+    let mut segment = Segment {
+        status: Vec::new(),
+        fetched: 0,
+        failure: None,
+    };
+
+    let ctx = segments.ctx;
+    let download = segments.download;
+    let mut selector = Selector::new(
+        segments.urls,
+        download.mirror_order,
+        &download.mirror_weights,
+    );
+    if download.spread_segments {
+        selector = selector.starting_at(index);
+    }
+
+    let retries = ctx.retries;
+    let mut previous: Option<String> = None;
+    for retry in 1..=retries {
+        let url = selector.next(&ctx.health);
+        if let Some(from) = previous.take().filter(|p| *p != url) {
+            segments.report.emit(Event::MirrorSwitched {
+                from,
+                to: url.clone(),
+            });
+        }
+
+        let start = std::time::Instant::now();
+        let before = segment.fetched;
+        let mut attempt = fetch_range(segments, &url, range, &mut segment.fetched).await;
+        ctx.record(&url, &attempt, segment.fetched - before, start.elapsed());
+
+        segment.status.push((url.clone(), attempt.status));
+        if attempt.is_complete() {
+            segment.failure = None;
+            break;
+        }
+        selector.failed(&url);
+        let retryable = attempt.is_retryable(segments.policy);
+        // An answer not worth retrying only rules out the mirror it came from:
+        let other_mirror = !retryable && attempt.is_mirror_failure() && selector.reject(&url);
+        let reason = attempt.reason();
+        segment.failure = Some(attempt.take_failure());
+        if !retryable && !other_mirror {
+            break;
+        }
+        if retryable && retry < retries {
+            let delay = segments.policy.delay(retry, attempt.retry_after);
+            segments.report.emit(Event::RetryScheduled {
+                url: url.clone(),
+                reason,
+                delay,
+            });
+            tokio::time::sleep(delay).await;
+        }
+        previous = Some(url);
+    }
+
+    segment
+}
+
+/// Removes the file at `path` when dropped, unless it is to be kept.
+struct Gaps<'a> {
+    path: &'a std::path::Path,
+    keep: bool,
+}
+
+impl Drop for Gaps<'_> {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_file(self.path);
+        }
+    }
+}
+
+/// Download the file found by `probe` into `file` at `path` using several connections at once.
+///
+/// The file is removed if that fails or gets cancelled: It has gaps where
+/// segments are missing, so it can not be resumed later. It is kept if a
+/// segment fails with `Failure::Unranged`, for the caller to download the
+/// file into over one connection.
+async fn download_segmented(
+    ctx: &Context,
+    urls: &[String],
+    download: &Download,
+    (file, path): (&std::fs::File, &std::path::Path),
+    probe: &Probe,
+    summary: &mut DownloadSummary,
+    report: &Report,
+) -> std::result::Result<(), Failure> {
+    let mut gaps = Gaps { path, keep: false };
+    let length = probe.length;
+    file.set_len(length).map_err(Failure::Io)?;
+
+    let message = file_name_message(&summary.file_name).into_owned();
+
+    let ranges = split_ranges(length, download.segments);
+    report.progress.setup(
+        Some(length),
+        &format!("{message} [{} segments]", ranges.len()),
+    );
+
+    let segments = Segments {
+        ctx,
+        download,
+        urls,
+        policy: download.retry_policy.as_ref().unwrap_or(&ctx.retry_policy),
+        path,
+        probe,
+        received: std::sync::atomic::AtomicU64::new(0),
+        report,
+    };
+    let results = futures::future::join_all(
+        ranges
+            .iter()
+            .enumerate()
+            .map(|(i, range)| download_segment(&segments, i, *range)),
+    )
+    .await;
+
+    let mut failure = None;
+    for s in results {
+        summary.status.extend(s.status);
+        summary.bytes_fetched += s.fetched;
+        // Falling back to one connection takes precedence over other failures:
+        failure = match (failure, s.failure) {
+            (Some(Failure::Unranged), _) | (_, Some(Failure::Unranged)) => Some(Failure::Unranged),
+            (failure, other) => failure.or(other),
+        };
     }
+    report.progress.set_message(&format!(
+        "{message} - {}",
+        if failure.is_none() { "Ok" } else { "FAILED" }
+    ));
+    gaps.keep = matches!(failure, None | Some(Failure::Unranged));
+    failure.map_or(Ok(()), Err)
 }
 
+/// Verify the file at `path` with the `verify_callback` and the `streaming`
+/// verifier. The latter is fed the file first if it was not fed while
+/// downloading.
 async fn verify_download(
     path: std::path::PathBuf,
     verify_callback: crate::Verify,
-    progress: crate::Progress,
+    streaming: Option<Streamed>,
+    report: &Report,
     message: &str,
 ) -> Verification {
-    let p = progress.clone();
-    let result =
-        tokio::task::spawn_blocking(move || verify_callback(path, &move |c: u64| p.progress(c)))
-            .await
-            .unwrap_or(crate::Verification::NotVerified);
-    progress.set_message(&format!(
+    report.emit(Event::VerificationStarted);
+    let p = report.progress.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let streamed = streaming.map_or(Verification::NotVerified, |(create, fed)| {
+            let fed = fed.map_or_else(
+                || {
+                    let mut verifier = create();
+                    let progress = p.clone();
+                    crate::verify::feed(&mut *verifier, &path, None, &move |c: u64| {
+                        progress.progress(c);
+                    })
+                    .map(|()| verifier)
+                },
+                Ok,
+            );
+            fed.map_or_else(crate::verify::unreadable, Streaming::finish)
+        });
+        streamed.and(verify_callback(path, &move |c: u64| p.progress(c)))
+    })
+    .await
+    .unwrap_or_else(|e| {
+        Verification::Failed(crate::verify::Failure::new("Verification panicked").cause(e))
+    });
+    report.progress.set_message(&format!(
         "{} - {}",
         message,
         match result {
             Verification::NotVerified => "not verified",
-            Verification::Failed => "FAILED",
+            Verification::Failed(_) => "FAILED",
             Verification::Ok => "Ok",
         }
     ));
-    progress.done();
+    report.emit(Event::VerificationFinished(result.clone()));
     result
 }
 
+fn file_name_message(path: &std::path::Path) -> std::borrow::Cow<'_, str> {
+    path.file_name()
+        .unwrap_or_else(|| std::ffi::OsStr::new("<unknown>"))
+        .to_string_lossy()
+}
+
+/// Download into `output` over one connection, retrying as often as configured.
+///
+/// Returns the last progress message.
+async fn download_single(
+    ctx: &Context,
+    urls: &[String],
+    download: &Download,
+    output: &mut Output,
+    state: &mut ResumeState,
+    summary: &mut DownloadSummary,
+    report: &Report,
+) -> std::result::Result<String, Failure> {
+    let retries = ctx.retries;
+    let policy = download.retry_policy.as_ref().unwrap_or(&ctx.retry_policy);
+    let mut selector = Selector::new(urls, download.mirror_order, &download.mirror_weights);
+    let mut failure = Failure::Status;
+    let mut previous: Option<String> = None;
+
+    for retry in 1..=retries {
+        let url = selector.next(&ctx.health);
+        if let Some(from) = previous.take().filter(|p| *p != url) {
+            report.emit(Event::MirrorSwitched {
+                from,
+                to: url.clone(),
+            });
+        }
+
+        let message = format!(
+            "{} {retry}/{retries}",
+            file_name_message(&summary.file_name)
+        );
+
+        let connection = ctx.connections.acquire(&url).await;
+        let start = std::time::Instant::now();
+        let before = state.fetched;
+        let mut attempt = download_url(ctx, &url, download, output, state, report, &message).await;
+        drop(connection);
+        ctx.record(&url, &attempt, state.fetched - before, start.elapsed());
+
+        summary.status.push((url.clone(), attempt.status));
+        summary.bytes_resumed = state.resumed;
+        summary.bytes_fetched = state.fetched;
+
+        if attempt.is_complete() {
+            if state.unchanged {
+                summary.action = FileAction::Unchanged;
+            }
+            finish_decoding(output, state).await?;
+            return output.flush().await.map(|()| message).map_err(Failure::Io);
+        }
+        selector.failed(&url);
+        let retryable = attempt.is_retryable(policy);
+        // An answer not worth retrying only rules out the mirror it came from:
+        let other_mirror = !retryable && attempt.is_mirror_failure() && selector.reject(&url);
+        let reason = attempt.reason();
+        failure = attempt.take_failure();
+        if !retryable && !other_mirror {
+            break;
+        }
+        if retryable && retry < retries {
+            let delay = policy.delay(retry, attempt.retry_after);
+            report.emit(Event::RetryScheduled {
+                url: url.clone(),
+                reason,
+                delay,
+            });
+            tokio::time::sleep(delay).await;
+        }
+        previous = Some(url);
+    }
+
+    Err(failure)
+}
+
+/// Does `download` need its data in a `.part` file before it is handed to the sink?
+///
+/// `Sink::File` always does. Other sinks get the data while it is received,
+/// unless the file is needed for the `verify_callback`, `segments`,
+/// extracting or resuming.
+fn spools(download: &Download) -> bool {
+    matches!(download.sink, Sink::File)
+        || !crate::verify::is_noop(&download.verify_callback)
+        || download.segments > 1
+        || download.extract.is_some()
+        || download.resume
+        || download.partial == PartialFile::Keep
+}
+
+/// How to decompress the data of `download` while it is received, `None` if
+/// it is not.
+///
+/// A `verify_callback` checking the compressed data needs that in a file, so
+/// those downloads get decompressed once they are complete, as do segmented
+/// downloads.
+fn decompress_on_the_fly(download: &Download) -> Option<(Decompression, VerifyTarget)> {
+    if download.decompress == Decompression::Off
+        || (download.verify_target == VerifyTarget::Compressed
+            && !crate::verify::is_noop(&download.verify_callback))
+    {
+        None
+    } else {
+        Some((download.decompress, download.verify_target))
+    }
+}
+
+/// Download the data of `download`, into the file at `path` if it `spools`
+/// and straight into its sink otherwise.
+async fn fetch(
+    ctx: &Context,
+    urls: Vec<String>,
+    download: &Download,
+    path: &std::path::Path,
+    summary: &mut DownloadSummary,
+    report: &Report,
+) -> std::result::Result<Fetched, Failure> {
+    if spools(download) {
+        return fetch_file(ctx, urls, download, path, summary, report).await;
+    }
+
+    let mut output = match &download.sink {
+        Sink::Writer(writer) => Output::Writer(writer.clone(), 0),
+        Sink::File | Sink::Memory => Output::Memory(Vec::new()),
+    };
+    let mut state = ResumeState {
+        streaming: download.streaming_verify.clone(),
+        verifier: download.streaming_verify.as_ref().map(|s| s()),
+        decompress: decompress_on_the_fly(download),
+        ..ResumeState::default()
+    };
+    let message = download_single(
+        ctx,
+        &urls,
+        download,
+        &mut output,
+        &mut state,
+        summary,
+        report,
+    )
+    .await?;
+    Ok(Fetched {
+        message,
+        verifier: state.verifier,
+        received: output.into_received(),
+        decompressed: state.decoder.is_some(),
+    })
+}
+
+/// Download into the file at `path`, either in segments or over one connection.
+async fn fetch_file(
+    ctx: &Context,
+    urls: Vec<String>,
+    download: &Download,
+    path: &std::path::Path,
+    summary: &mut DownloadSummary,
+    report: &Report,
+) -> std::result::Result<Fetched, Failure> {
+    let decompress = decompress_on_the_fly(download);
+    // Decompressed data can not be continued with a `Range` request:
+    let mut file = if download.resume && decompress.is_none() {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+    } else {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)
+    }
+    .map_err(Failure::Io)?;
+
+    let mut state = ResumeState {
+        offset: file.metadata().map_or(0, |m| m.len()),
+        path: decompress.is_none().then(|| path.to_path_buf()),
+        streaming: download.streaming_verify.clone(),
+        decompress,
+        ..ResumeState::default()
+    };
+    if state.offset > 0 {
+        state.validator = crate::metadata::Metadata::load(path).and_then(|m| m.validator());
+        if state.validator.is_none() {
+            // There is no telling whether the server still has the file the
+            // existing bytes came from: Start over.
+            file.set_len(0).map_err(Failure::Io)?;
+            state.offset = 0;
+        }
+    }
+    if let Some(streaming) = &state.streaming {
+        // Verify what is there already when resuming:
+        let mut verifier = streaming();
+        let (prefix, length) = (path.to_path_buf(), state.offset);
+        let fed = tokio::task::spawn_blocking(move || {
+            crate::verify::feed(&mut *verifier, &prefix, Some(length), &|_| {}).map(|()| verifier)
+        })
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+        state.verifier = Some(fed.map_err(Failure::Io)?);
+    }
+
+    let segmented =
+        if download.segments > 1 && download.method == reqwest::Method::GET && state.offset == 0 {
+            let mut selector =
+                Selector::new(&urls, download.mirror_order, &download.mirror_weights);
+            probe(ctx, download, &selector.next(&ctx.health))
+                .await
+                .filter(|p| p.length >= 2 * MIN_SEGMENT_SIZE)
+        } else {
+            None
+        };
+
+    if let Some(probe) = segmented {
+        match download_segmented(ctx, &urls, download, (&file, path), &probe, summary, report).await
+        {
+            Ok(()) => {
+                return Ok(Fetched {
+                    message: file_name_message(&summary.file_name).into_owned(),
+                    verifier: None,
+                    received: Received::Spooled,
+                    decompressed: false,
+                })
+            }
+            // The server does not send ranges after all: Start over with one connection.
+            Err(Failure::Unranged) => {
+                file.set_len(0).map_err(Failure::Io)?;
+                state.fetched = summary.bytes_fetched;
+            }
+            Err(failure) => return Err(failure),
+        }
+    }
+
+    file.seek(SeekFrom::Start(state.offset))
+        .map_err(Failure::Io)?;
+    let mut output = Output::File(std::io::BufWriter::new(file));
+    let message = download_single(
+        ctx,
+        &urls,
+        download,
+        &mut output,
+        &mut state,
+        summary,
+        report,
+    )
+    .await?;
+    Ok(Fetched {
+        message,
+        verifier: state.verifier,
+        received: Received::Spooled,
+        decompressed: state.decoder.is_some(),
+    })
+}
+
+/// The name of the file to download into before it is moved into place.
+fn temp_file_name(
+    file_name: &std::path::Path,
+    temp_dir: Option<&std::path::Path>,
+) -> std::path::PathBuf {
+    let mut name = temp_dir.map_or_else(
+        || file_name.as_os_str().to_owned(),
+        |d| {
+            d.join(file_name.file_name().unwrap_or_default())
+                .into_os_string()
+        },
+    );
+    name.push(".part");
+    std::path::PathBuf::from(name)
+}
+
+/// A name for the file to download `urls` into for sinks that are not files
+/// (or files named after the response).
+///
+/// The name is derived from the URLs, so that later attempts find the data
+/// earlier ones left behind.
+fn url_temp_file_name(temp_dir: &std::path::Path, urls: &[String]) -> std::path::PathBuf {
+    // FNV-1a, which is stable across runs and builds unlike `DefaultHasher`:
+    let hash = urls
+        .iter()
+        .flat_map(|u| u.bytes().chain(std::iter::once(0)))
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    temp_dir.join(format!(".{}-{:016x}.part", env!("CARGO_PKG_NAME"), hash))
+}
+
+/// Make sure the data in `path` actually made it to disk.
+fn sync(path: &std::path::Path) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .sync_all()
+}
+
+/// Move the downloaded file into place.
+///
+/// If `from` and `to` are on different file systems, the file is copied into
+/// a `.part` file next to `to` first, so that `to` never holds a partial copy.
+fn persist(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {}
+        result => return result,
+    }
+    let part = temp_file_name(to, None);
+    let copied = std::fs::copy(from, &part)
+        .and_then(|_| sync(&part))
+        .and_then(|()| std::fs::rename(&part, to));
+    if copied.is_err() {
+        let _ = std::fs::remove_file(&part);
+    }
+    copied?;
+    std::fs::remove_file(from)
+}
+
+/// Hand the data `received` (into `temp`) over to the `sink`.
+async fn deliver(
+    temp: &std::path::Path,
+    received: Received,
+    sink: &Sink,
+    summary: &mut DownloadSummary,
+) -> std::io::Result<()> {
+    match received {
+        Received::Spooled => {}
+        Received::Memory(data) => {
+            summary.data = Some(data);
+            return Ok(());
+        }
+        Received::Written => return Ok(()),
+    }
+    match sink {
+        Sink::File => persist(temp, &summary.file_name),
+        Sink::Memory => {
+            summary.data = Some(std::fs::read(temp)?);
+            std::fs::remove_file(temp)
+        }
+        Sink::Writer(writer) => {
+            use std::io::Read;
+            use tokio::io::AsyncWriteExt;
+
+            let mut file = std::fs::File::open(temp)?;
+            let mut writer = writer.lock().await;
+            let mut buffer = vec![0; 64 * 1024];
+            loop {
+                let count = file.read(&mut buffer)?;
+                if count == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..count]).await?;
+            }
+            writer.flush().await?;
+            drop(writer);
+            std::fs::remove_file(temp)
+        }
+    }
+}
+
+/// Extensions made up of several parts, which are kept together.
+const COMPOUND_EXTENSIONS: [&str; 4] = [".tar.gz", ".tar.xz", ".tar.zst", ".tar.bz2"];
+
+/// Find a file name that is not used yet by appending ` (1)`, ` (2)`, ... to the
+/// file stem of `path`.
+fn free_file_name(path: &std::path::Path) -> std::path::PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let lowercase = name.to_ascii_lowercase();
+    let split = COMPOUND_EXTENSIONS
+        .iter()
+        .find(|e| lowercase.len() > e.len() && lowercase.ends_with(*e))
+        .map_or_else(
+            || name.rfind('.').filter(|i| *i > 0).unwrap_or(name.len()),
+            |e| name.len() - e.len(),
+        );
+    let (stem, extension) = name.split_at(split);
+    (1..=u32::MAX)
+        .map(|i| path.with_file_name(format!("{stem} ({i}){extension}")))
+        .find(|p| !p.exists())
+        .expect("There is always a free file name")
+}
+
+fn discard(path: &std::path::Path, partial: PartialFile) {
+    if partial == PartialFile::Remove {
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(crate::metadata::Metadata::sidecar(path));
+    }
+}
+
+/// Apply the `ExistingFile` policy of `download` to the existing file.
+///
+/// Returns `false` if the file does not need to get downloaded.
+async fn handle_existing(
+    download: &Download,
+    summary: &mut DownloadSummary,
+    report: &Report,
+) -> bool {
+    match download.existing {
+        ExistingFile::Fail | ExistingFile::Overwrite | ExistingFile::Update => {
+            summary.action = FileAction::Overwritten;
+        }
+        ExistingFile::Skip => {
+            summary.action = FileAction::Skipped;
+            return false;
+        }
+        ExistingFile::SkipIfVerified => {
+            let verified = verify_download(
+                summary.file_name.clone(),
+                download.verify_callback.clone(),
+                download.streaming_verify.clone().map(|s| (s, None)),
+                report,
+                &file_name_message(&summary.file_name),
+            )
+            .await;
+            if verified.is_ok() {
+                summary.verified = verified;
+                summary.action = FileAction::Skipped;
+                return false;
+            }
+            summary.action = FileAction::Overwritten;
+        }
+        ExistingFile::Rename => {
+            summary.file_name = free_file_name(&summary.file_name);
+            summary.action = FileAction::Renamed;
+        }
+    }
+    true
+}
+
 async fn download(
-    client: reqwest::Client,
+    ctx: &Context,
     mut download: Download,
-    retries: u16,
+    events: crate::event::Emitter,
 ) -> Result<DownloadSummary> {
     let mut summary = DownloadSummary {
         status: Vec::new(),
         file_name: std::mem::take(&mut download.file_name),
         verified: Verification::NotVerified,
+        bytes_resumed: 0,
+        bytes_fetched: 0,
+        action: FileAction::Downloaded,
+        data: None,
+        response: None,
+        extracted: Vec::new(),
     };
 
-    let mut urls = std::mem::take(&mut download.urls);
+    let urls = download.urls.clone();
     assert!(!urls.is_empty());
 
-    let mut progress = download.progress.expect("This has been set!").clone();
-    let mut message = String::new();
+    let report = Report::new(
+        download.progress.clone().expect("This has been set!"),
+        events,
+    );
+    let token = download.cancel.clone();
 
-    let mut download_successful = false;
+    if ctx.is_cancelled(token.as_ref()) {
+        report.progress.done();
+        return Err(Error::Cancelled(summary));
+    }
+    report.emit(Event::Started);
 
-    if let Ok(file) = std::fs::OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(&summary.file_name)
-    {
-        let mut writer = std::io::BufWriter::new(file);
-
-        for retry in 1..=retries {
-            let url = select_url(&urls);
-
-            message = format!(
-                "{} {}/{}",
-                &summary
-                    .file_name
-                    .file_name()
-                    .unwrap_or_else(|| std::ffi::OsStr::new("<unknown>"))
-                    .to_string_lossy(),
-                retry,
-                retries,
-            );
+    let fixed_name = matches!(download.sink, Sink::File) && download.naming == Naming::Fixed;
+    if fixed_name && summary.file_name.exists() {
+        if download.existing == ExistingFile::Fail {
+            return Err(Error::FileExists(summary));
+        }
+        if !handle_existing(&download, &mut summary, &report).await {
+            report.progress.done();
+            return Ok(summary);
+        }
+        if download.existing == ExistingFile::Update {
+            if let Some(metadata) = crate::metadata::Metadata::load(&summary.file_name) {
+                download.headers.extend(metadata.conditional_headers());
+            }
+        }
+    }
+
+    let temp = if fixed_name {
+        temp_file_name(&summary.file_name, download.temp_dir.as_deref())
+    } else {
+        url_temp_file_name(
+            download.temp_dir.as_deref().expect("This has been set!"),
+            &urls,
+        )
+    };
+    let fetched = ctx
+        .until_cancelled(
+            token.as_ref(),
+            fetch(ctx, urls, &download, &temp, &mut summary, &report),
+        )
+        .await;
+    let fetched = fetched.map(|r| {
+        r.and_then(|f| {
+            if matches!(f.received, Received::Spooled) {
+                sync(&temp).map_err(Failure::Io)?;
+            }
+            Ok(f)
+        })
+    });
+    let fetched = match fetched {
+        Some(Ok(f)) => f,
+        Some(Err(failure)) => {
+            report.progress.done();
+            discard(&temp, download.partial);
+            return Err(failure.into_error(summary));
+        }
+        None => {
+            report.progress.done();
+            discard(&temp, download.partial);
+            return Err(Error::Cancelled(summary));
+        }
+    };
+    // The file is complete, there is nothing left to resume:
+    let _ = std::fs::remove_file(crate::metadata::Metadata::sidecar(&temp));
+    if summary.action == FileAction::Unchanged {
+        report.progress.done();
+        discard(&temp, PartialFile::Remove);
+        return Ok(summary);
+    }
+
+    store(ctx, download, &temp, summary, &report, fetched).await
+}
 
-            let s = reqwest::StatusCode::from_u16(
-                download_url(
-                    client.clone(),
-                    url.clone(),
-                    &mut writer,
-                    &mut progress,
-                    &message,
-                )
-                .await,
+/// Set the modification time of the downloaded file and store its metadata,
+/// as configured for `download`.
+fn finish_file(
+    ctx: &Context,
+    download: &Download,
+    summary: &DownloadSummary,
+) -> std::io::Result<()> {
+    let response = summary.response.as_deref();
+    if download.preserve_mtime.unwrap_or(ctx.preserve_mtime) {
+        if let Some(modified) = response.and_then(crate::metadata::Response::last_modified) {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&summary.file_name)?
+                .set_modified(modified)?;
+        }
+    }
+    if download.existing == ExistingFile::Update {
+        response
+            .map(crate::metadata::Response::metadata)
+            .unwrap_or_default()
+            .store(&summary.file_name)?;
+    }
+    Ok(())
+}
+
+/// The format to `decompress` data sent with the `content_encoding` in,
+/// `None` to keep it as is.
+fn compression(
+    decompress: Decompression,
+    content_encoding: Option<&str>,
+) -> Option<crate::decompress::Format> {
+    match decompress {
+        Decompression::Off => None,
+        Decompression::ContentEncoding => {
+            content_encoding.and_then(crate::decompress::Format::from_content_encoding)
+        }
+        Decompression::Always(format) => Some(format),
+    }
+}
+
+/// Decompress the file at `temp` in `format` into a new file next to it,
+/// remove the old file and point `temp` to the new one.
+///
+/// A partially decompressed file can not be resumed, so `partial` is set to
+/// remove it if the download fails later. The file at `temp` is discarded as
+/// `partial` says if decompressing fails.
+async fn unpack(
+    format: crate::decompress::Format,
+    temp: &mut std::path::PathBuf,
+    partial: &mut PartialFile,
+    report: &Report,
+    message: &str,
+) -> std::io::Result<()> {
+    let from = temp.clone();
+    let to = temp.with_extension("unpacked.part");
+    report.progress.setup(
+        std::fs::metadata(&from).ok().map(|m| m.len()),
+        &format!("{message} - decompressing"),
+    );
+    let p = report.progress.clone();
+    let target = to.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        crate::decompress::decompress(format, &from, &target, &move |c: u64| p.progress(c))
+    })
+    .await
+    .unwrap_or_else(|e| Err(std::io::Error::other(e)));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&to);
+        report.progress.done();
+        discard(temp, *partial);
+        return Err(e);
+    }
+    std::fs::remove_file(&temp)?;
+    report
+        .progress
+        .setup(std::fs::metadata(&to).ok().map(|m| m.len()), message);
+    *temp = to;
+    *partial = PartialFile::Remove;
+    Ok(())
+}
+
+/// Extract the archive at `temp` as requested by `extraction`.
+///
+/// Returns the extracted files.
+async fn extract(
+    extraction: &crate::extract::Extraction,
+    temp: &std::path::Path,
+    decompressed: bool,
+    summary: &DownloadSummary,
+    report: &Report,
+    message: &str,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let archive = extraction
+        .archive
+        .or_else(|| crate::extract::Archive::from_file_name(&summary.file_name))
+        .or_else(|| {
+            let url = &summary.response.as_ref()?.url;
+            crate::extract::Archive::from_file_name(std::path::Path::new(
+                url.split(['?', '#']).next()?,
+            ))
+        })
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Can not guess the archive format.",
             )
-            .unwrap_or(reqwest::StatusCode::BAD_REQUEST);
+        })?;
+    let archive = if decompressed {
+        archive.decompressed()
+    } else {
+        archive
+    };
 
-            summary.status.push((url.clone(), s.as_u16()));
+    report.progress.setup(
+        std::fs::metadata(temp).ok().map(|m| m.len()),
+        &format!("{message} - extracting"),
+    );
+    let p = report.progress.clone();
+    let temp = temp.to_path_buf();
+    let directory = extraction.directory.clone();
+    let strip = extraction.strip_components;
+    tokio::task::spawn_blocking(move || {
+        crate::extract::extract(archive, &temp, &directory, strip, &move |c: u64| {
+            p.progress(c);
+        })
+    })
+    .await
+    .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+}
 
-            if s.is_server_error() {
-                urls = urls
-                    .iter()
-                    .filter_map(|u| if u == &url { Some(u.clone()) } else { None })
-                    .collect();
-                if urls.is_empty() {
-                    break;
-                }
-            }
+/// Hand the data `received` (into `temp`) over to the sink and run the hooks
+/// of `download`.
+async fn land(
+    ctx: &Context,
+    download: &Download,
+    (temp, received): (&std::path::Path, Received),
+    partial: PartialFile,
+    mut summary: DownloadSummary,
+) -> Result<DownloadSummary> {
+    if let Err(e) = deliver(temp, received, &download.sink, &mut summary).await {
+        discard(temp, partial);
+        return Err(Error::File(summary, e));
+    }
+    if matches!(download.sink, Sink::File) {
+        if let Err(e) = finish_file(ctx, download, &summary) {
+            return Err(Error::File(summary, e));
+        }
+    }
 
-            if s.is_success() {
-                download_successful = true;
-                break;
+    for hook in &download.hooks {
+        let (s, result) = hook.run(summary).await;
+        summary = s;
+        if let Err(e) = result {
+            return Err(Error::Hook(summary, e));
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Verify the data that was `fetched` (into `temp`, if it spooled), decompress
+/// and extract it if requested and hand it over to the sink.
+async fn store(
+    ctx: &Context,
+    mut download: Download,
+    temp: &std::path::Path,
+    mut summary: DownloadSummary,
+    report: &Report,
+    fetched: Fetched,
+) -> Result<DownloadSummary> {
+    let message = fetched.message.as_str();
+    summary.response = report.response().map(Box::new);
+    let format = compression(
+        download.decompress,
+        summary
+            .response
+            .as_deref()
+            .and_then(|r| r.header(reqwest::header::CONTENT_ENCODING)),
+    );
+    // Data that was not decompressed while it was received is decompressed now:
+    let unpack_format = format.filter(|_| !fetched.decompressed);
+    let mut temp = temp.to_path_buf();
+    let mut partial = download.partial;
+
+    let streamed = if let Some(format) =
+        unpack_format.filter(|_| download.verify_target == VerifyTarget::Decompressed)
+    {
+        if let Err(e) = unpack(format, &mut temp, &mut partial, report, message).await {
+            return Err(Error::Decompression(summary, e));
+        }
+        // The decompressed data needs to be verified instead of what was received:
+        None
+    } else {
+        fetched.verifier
+    };
+
+    let verified = ctx
+        .until_cancelled(
+            download.cancel.as_ref(),
+            verify_download(
+                temp.clone(),
+                std::mem::replace(&mut download.verify_callback, crate::verify::noop()),
+                download.streaming_verify.clone().map(|s| (s, streamed)),
+                report,
+                message,
+            ),
+        )
+        .await;
+    let Some(verified) = verified else {
+        report.progress.done();
+        discard(&temp, partial);
+        return Err(Error::Cancelled(summary));
+    };
+    summary.verified = verified;
+    if summary.verified.is_failed() {
+        report.progress.done();
+        discard(&temp, partial);
+        return Err(Error::Verification(summary));
+    }
+
+    if let Some(format) =
+        unpack_format.filter(|_| download.verify_target == VerifyTarget::Compressed)
+    {
+        if let Err(e) = unpack(format, &mut temp, &mut partial, report, message).await {
+            return Err(Error::Decompression(summary, e));
+        }
+    }
+    report.progress.done();
+
+    if download.naming == Naming::Response {
+        let response = summary.response.as_deref();
+        let name = crate::download::file_name_from_response(
+            response.map_or(&download.urls[0], |r| &r.url),
+            response.and_then(|r| r.header(reqwest::header::CONTENT_DISPOSITION)),
+            response.and_then(|r| r.content_type.as_deref()),
+        );
+        summary.file_name = download
+            .output_path
+            .as_ref()
+            .expect("This has been set!")
+            .join(name);
+        if matches!(download.sink, Sink::File) && summary.file_name.exists() {
+            if download.existing == ExistingFile::Fail {
+                discard(&temp, PartialFile::Remove);
+                return Err(Error::FileExists(summary));
+            }
+            if !handle_existing(&download, &mut summary, report).await {
+                discard(&temp, PartialFile::Remove);
+                return Ok(summary);
             }
         }
     }
 
-    if !download_successful {
-        return Err(Error::Download(summary));
+    if let Some(extraction) = &download.extract {
+        match extract(
+            extraction,
+            &temp,
+            format.is_some(),
+            &summary,
+            report,
+            message,
+        )
+        .await
+        {
+            Ok(files) => summary.extracted = files,
+            Err(e) => {
+                discard(&temp, partial);
+                return Err(Error::Extraction(summary, e));
+            }
+        }
+        report.progress.done();
     }
 
-    summary.verified = verify_download(
-        summary.file_name.clone(),
-        std::mem::replace(&mut download.verify_callback, crate::verify::noop()),
-        progress.clone(),
-        &message,
-    )
-    .await;
-    if summary.verified == Verification::Failed {
-        return Err(Error::Verification(summary));
+    land(ctx, &download, (&temp, fetched.received), partial, summary).await
+}
+
+/// Run `downloads` with up to `parallel_requests` of them at the same time.
+///
+/// Downloads are started in order, except that downloads from hosts that have
+/// as many downloads running as they allow connections are passed over for
+/// downloads from other hosts.
+async fn schedule(ctx: &Context, downloads: Vec<Download>) -> Vec<Result<DownloadSummary>> {
+    let mut pending: std::collections::VecDeque<(String, crate::event::Emitter, Download)> =
+        downloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let events = ctx.events.emitter(i, &d.file_name);
+                events.emit(Event::Queued);
+                (crate::mirror::host(&d.urls[0]), events, d)
+            })
+            .collect();
+    let mut active = std::collections::HashMap::<String, u16>::new();
+    let mut running = futures::stream::FuturesUnordered::new();
+    let mut results = Vec::with_capacity(pending.len());
+
+    loop {
+        while running.len() < usize::from(ctx.connections.parallel_requests.max(1)) {
+            let Some(index) = pending.iter().position(|(host, _, _)| {
+                ctx.connections
+                    .limit(host)
+                    .is_none_or(|l| active.get(host).copied().unwrap_or_default() < l)
+            }) else {
+                break;
+            };
+            let (host, events, d) = pending.remove(index).expect("Index is valid");
+            *active.entry(host.clone()).or_default() += 1;
+            running.push(async move {
+                let result = download(ctx, d, events.clone()).await;
+                events.emit(match &result {
+                    Ok(summary) => Event::Completed {
+                        action: summary.action,
+                        verified: summary.verified.clone(),
+                    },
+                    Err(e) => Event::Failed {
+                        reason: e.to_string(),
+                    },
+                });
+                (host, result)
+            });
+        }
+
+        let Some((host, result)) = running.next().await else {
+            break;
+        };
+        if let Some(count) = active.get_mut(&host) {
+            *count -= 1;
+        }
+        results.push(result);
     }
 
-    Ok(summary)
+    results
 }
 
-/// Run the provided list of `downloads`, using the provided `client`
+/// Run the provided list of `downloads` in the provided `ctx` on `runtime`,
+/// blocking until they are done.
 pub(crate) fn run(
-    client: &mut reqwest::Client,
+    runtime: &tokio::runtime::Handle,
+    ctx: Context,
     downloads: Vec<Download>,
-    retries: u16,
-    parallel_requests: u16,
     spin: &dyn Fn(),
 ) -> Vec<Result<DownloadSummary>> {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    let cl = client.clone();
-
-    let result = rt.spawn(async move {
-        stream::iter(downloads)
-            .map(move |d| download(cl.clone(), d, retries))
-            .buffer_unordered(parallel_requests as usize)
-            .collect::<Vec<Result<DownloadSummary>>>()
-            .await
-    });
+    let result = runtime.spawn(async move { schedule(&ctx, downloads).await });
 
     spin();
 
-    rt.block_on(result).unwrap()
+    runtime.block_on(result).unwrap()
 }
 
+/// Run the provided list of `downloads` in the provided `ctx`, either on
+/// `runtime` or, if that is `None`, within the calling task.
 pub(crate) async fn async_run(
-    client: &mut reqwest::Client,
+    runtime: Option<tokio::runtime::Handle>,
+    ctx: Context,
     downloads: Vec<Download>,
-    retries: u16,
-    parallel_requests: u16,
-    spin: &dyn Fn(),
+    spin: &(dyn Fn() + Sync),
 ) -> Vec<Result<DownloadSummary>> {
-    let cl = client.clone();
-
-    let result = tokio::spawn(async move {
-        stream::iter(downloads)
-            .map(move |d| download(cl.clone(), d, retries))
-            .buffer_unordered(parallel_requests as usize)
-            .collect::<Vec<Result<DownloadSummary>>>()
+    let result = match runtime {
+        Some(runtime) => runtime
+            .spawn(async move { schedule(&ctx, downloads).await })
             .await
-    })
-    .await;
+            .unwrap(),
+        None => schedule(&ctx, downloads).await,
+    };
 
     spin();
 
-    result.unwrap()
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_resumed_are_counted_once() {
+        let mut output = Output::Memory(vec![0; 100]);
+        let mut state = ResumeState {
+            offset: 100,
+            ..ResumeState::default()
+        };
+        state.resume();
+        assert_eq!(state.resumed, 100);
+
+        // An interrupted transfer continued within the same run:
+        state.offset += 50;
+        state.fetched += 50;
+        state.resume();
+        assert_eq!(state.resumed, 100);
+
+        restart(&mut output, &mut state).unwrap();
+        assert_eq!(state.resumed, 0);
+        state.offset += 20;
+        state.fetched += 20;
+        state.resume();
+        assert_eq!(state.resumed, 0);
+    }
+
+    #[test]
+    fn free_file_names() {
+        let dir = std::env::temp_dir().join(format!("free-file-name-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(
+            free_file_name(&dir.join("file.txt")),
+            dir.join("file (1).txt")
+        );
+        assert_eq!(free_file_name(&dir.join("README")), dir.join("README (1)"));
+        assert_eq!(
+            free_file_name(&dir.join("archive.tar.gz")),
+            dir.join("archive (1).tar.gz")
+        );
+        assert_eq!(
+            free_file_name(&dir.join("Archive.TAR.XZ")),
+            dir.join("Archive (1).TAR.XZ")
+        );
+        assert_eq!(
+            free_file_name(&dir.join("data.tar.zst")),
+            dir.join("data (1).tar.zst")
+        );
+        assert_eq!(
+            free_file_name(&dir.join("backup.gz")),
+            dir.join("backup (1).gz")
+        );
+
+        std::fs::write(dir.join("archive (1).tar.gz"), b"").unwrap();
+        assert_eq!(
+            free_file_name(&dir.join("archive.tar.gz")),
+            dir.join("archive (2).tar.gz")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn split_ranges_covers_the_file() {
+        let length = 10 * MIN_SEGMENT_SIZE + 3;
+        let ranges = split_ranges(length, 4);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[0].0, 0);
+        assert_eq!(ranges[3].1, length);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn split_ranges_keeps_segments_large() {
+        assert_eq!(
+            split_ranges(3 * MIN_SEGMENT_SIZE, 8),
+            vec![
+                (0, MIN_SEGMENT_SIZE),
+                (MIN_SEGMENT_SIZE, 2 * MIN_SEGMENT_SIZE),
+                (2 * MIN_SEGMENT_SIZE, 3 * MIN_SEGMENT_SIZE),
+            ]
+        );
+        assert_eq!(split_ranges(100, 4), vec![(0, 100)]);
+        assert_eq!(split_ranges(0, 4), Vec::new());
+    }
+
+    #[test]
+    fn parse_content_range_values() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/1000"),
+            Some((Some(100), Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 0-99/*"), Some((Some(0), None)));
+        assert_eq!(
+            parse_content_range("bytes */1000"),
+            Some((None, Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 100-199"), None);
+        assert_eq!(parse_content_range("items 0-9/10"), None);
+        assert_eq!(parse_content_range("bytes x-199/1000"), None);
+    }
 }
@@ -0,0 +1,138 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Bandwidth limiting
+
+use std::convert::TryFrom;
+
+/// Never sleep longer than this at once, so that rate changes take effect quickly.
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_millis(100);
+
+// ----------------------------------------------------------------------
+// - Bucket:
+// ----------------------------------------------------------------------
+
+/// A token bucket holding up to one second worth of bytes.
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes per second, `None` for no limit.
+    rate: Option<u64>,
+    /// Bytes that can be received right away. Negative if more bytes were
+    /// received than the bucket held.
+    tokens: i64,
+    /// When `tokens` was last refilled.
+    last: std::time::Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: u64) {
+        let now = std::time::Instant::now();
+        let added =
+            u128::from(rate) * now.saturating_duration_since(self.last).as_nanos() / 1_000_000_000;
+        if added > 0 {
+            let capacity = i64::try_from(rate).unwrap_or(i64::MAX);
+            let added = i64::try_from(added).unwrap_or(i64::MAX);
+            self.tokens = self.tokens.saturating_add(added).min(capacity);
+            self.last = now;
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Limiter:
+// ----------------------------------------------------------------------
+
+/// Limits the rate bytes are received at
+///
+/// All clones of a `Limiter` share the same budget, so one `Limiter` can be used
+/// for several `Download`s to limit their combined bandwidth. The rate can be
+/// changed at any time, even while downloads are running.
+#[derive(Clone, Debug)]
+pub struct Limiter {
+    bucket: std::sync::Arc<std::sync::Mutex<Bucket>>,
+}
+
+impl Limiter {
+    /// Create a `Limiter` allowing `bytes_per_second`.
+    #[must_use]
+    pub fn new(bytes_per_second: u64) -> Self {
+        let limiter = Self::unlimited();
+        limiter.set_rate(Some(bytes_per_second));
+        limiter
+    }
+
+    /// Create a `Limiter` that does not limit anything (yet).
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self {
+            bucket: std::sync::Arc::new(std::sync::Mutex::new(Bucket {
+                rate: None,
+                tokens: 0,
+                last: std::time::Instant::now(),
+            })),
+        }
+    }
+
+    /// The current limit in bytes per second, `None` if there is no limit.
+    #[must_use]
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .rate
+    }
+
+    /// Change the limit to `bytes_per_second`, `None` removes the limit.
+    pub fn set_rate(&self, bytes_per_second: Option<u64>) {
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        bucket.rate = bytes_per_second.filter(|r| *r > 0);
+        bucket.tokens = bucket.tokens.min(0);
+        bucket.last = std::time::Instant::now();
+        drop(bucket);
+    }
+
+    /// Account for `bytes` that were received, waiting until the budget
+    /// allows for more.
+    pub(crate) async fn consume(&self, bytes: u64) {
+        {
+            let mut bucket = self
+                .bucket
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if let Some(rate) = bucket.rate {
+                bucket.refill(rate);
+                bucket.tokens = bucket
+                    .tokens
+                    .saturating_sub(i64::try_from(bytes).unwrap_or(i64::MAX));
+            }
+        }
+
+        while let Some(wait) = self.wait_time() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// How long to wait before the budget is positive again, `None` if there
+    /// is no need to wait.
+    fn wait_time(&self) -> Option<std::time::Duration> {
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let rate = bucket.rate?;
+        bucket.refill(rate);
+        let missing = u64::try_from(bucket.tokens.checked_neg()?).ok()?;
+        drop(bucket);
+        if missing == 0 {
+            return None;
+        }
+        let wait = std::time::Duration::from_nanos(
+            u64::try_from(u128::from(missing) * 1_000_000_000 / u128::from(rate))
+                .unwrap_or(u64::MAX),
+        );
+        Some(wait.min(MAX_SLEEP))
+    }
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Cancellation of running downloads
+
+// ----------------------------------------------------------------------
+// - Token:
+// ----------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: std::sync::atomic::AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+/// A handle to cancel downloads with
+///
+/// All clones of a `Token` share the same state, so a clone can be kept
+/// around (e.g. in a GUI thread) to cancel the downloads that were handed the
+/// original. Once cancelled, a `Token` stays cancelled, except for the token
+/// of a `Downloader`, which is reset whenever a new batch of downloads starts.
+#[derive(Clone, Debug, Default)]
+pub struct Token {
+    inner: std::sync::Arc<Inner>,
+}
+
+impl Token {
+    /// Create a new `Token` that is not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all downloads using this `Token`.
+    ///
+    /// Requests that are in flight are aborted and the downloads end with
+    /// `Error::Cancelled`. Downloads that did not start yet will not start at all.
+    pub fn cancel(&self) {
+        self.inner
+            .cancelled
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Has `cancel` been called on this `Token` (or any of its clones)?
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner
+            .cancelled
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Make this `Token` (and all of its clones) usable again.
+    pub(crate) fn reset(&self) {
+        self.inner
+            .cancelled
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Wait until this `Token` gets cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Decompression of downloaded data
+
+// ----------------------------------------------------------------------
+// - Format:
+// ----------------------------------------------------------------------
+
+/// A compression format
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    /// `gzip`, as in `.gz` files.
+    Gzip,
+    /// `xz`, as in `.xz` files.
+    Xz,
+    /// `zstd`, as in `.zst` files.
+    Zstd,
+}
+
+impl Format {
+    /// The format named in a `Content-Encoding` header, `None` for `identity`
+    /// and unknown encodings.
+    #[must_use]
+    pub fn from_content_encoding(encoding: &str) -> Option<Self> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "xz" => Some(Self::Xz),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Self::Gzip => "gzip",
+                Self::Xz => "xz",
+                Self::Zstd => "zstd",
+            }
+        )
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Decompression:
+// ----------------------------------------------------------------------
+
+/// When to decompress the data of a `Download`
+///
+/// Decompressing needs the `decompress` feature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decompression {
+    /// Keep the data as it was received.
+    Off,
+    /// Decompress the data if the `Content-Encoding` header of the response
+    /// names a known `Format`.
+    ContentEncoding,
+    /// Always decompress the data in the given `Format`.
+    Always(Format),
+}
+
+/// Which data the `verify_callback` of a decompressed `Download` gets to see
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerifyTarget {
+    /// The data as it was received.
+    Compressed,
+    /// The data after decompressing it.
+    Decompressed,
+}
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// Counts the bytes read through it.
+#[cfg(feature = "decompress")]
+pub(crate) struct Counter<'a, R> {
+    inner: R,
+    count: u64,
+    progress: &'a crate::SimpleProgress,
+}
+
+#[cfg(feature = "decompress")]
+impl<'a, R> Counter<'a, R> {
+    /// Report the number of bytes read from `inner` to `progress`.
+    pub(crate) fn new(inner: R, progress: &'a crate::SimpleProgress) -> Self {
+        Self {
+            inner,
+            count: 0,
+            progress,
+        }
+    }
+}
+
+#[cfg(feature = "decompress")]
+impl<R: std::io::Read> std::io::Read for Counter<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.count += count as u64;
+        (self.progress)(self.count);
+        Ok(count)
+    }
+}
+
+/// Wrap `input` into a reader decompressing it in `format`.
+#[cfg(feature = "decompress")]
+pub(crate) fn decoder<'a, R: std::io::BufRead + 'a>(
+    format: Format,
+    input: R,
+) -> std::io::Result<Box<dyn std::io::Read + 'a>> {
+    Ok(match format {
+        Format::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(input)),
+        Format::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(input)),
+        Format::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(input)?),
+    })
+}
+
+/// Decompresses data handed to it piece by piece, while it is received.
+#[cfg(feature = "decompress")]
+pub(crate) enum Decoder {
+    Gzip(flate2::write::MultiGzDecoder<Vec<u8>>),
+    Xz(xz2::write::XzDecoder<Vec<u8>>),
+    Zstd(zstd::stream::zio::Writer<Vec<u8>, zstd::stream::raw::Decoder<'static>>),
+}
+
+#[cfg(feature = "decompress")]
+impl Decoder {
+    /// A `Decoder` for data in `format`.
+    pub(crate) fn new(format: Format) -> std::io::Result<Self> {
+        Ok(match format {
+            Format::Gzip => Self::Gzip(flate2::write::MultiGzDecoder::new(Vec::new())),
+            Format::Xz => Self::Xz(xz2::write::XzDecoder::new_multi_decoder(Vec::new())),
+            Format::Zstd => Self::Zstd(zstd::stream::zio::Writer::new(
+                Vec::new(),
+                zstd::stream::raw::Decoder::new()?,
+            )),
+        })
+    }
+
+    /// The decompressed data not taken yet.
+    fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            Self::Gzip(d) => d.get_mut(),
+            Self::Xz(d) => d.get_mut(),
+            Self::Zstd(d) => d.writer_mut(),
+        }
+    }
+
+    /// Decompress `input`, returning as much decompressed data as is available.
+    pub(crate) fn update(&mut self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip(d) => std::io::Write::write_all(d, input)?,
+            Self::Xz(d) => std::io::Write::write_all(d, input)?,
+            Self::Zstd(d) => std::io::Write::write_all(d, input)?,
+        }
+        Ok(std::mem::take(self.output()))
+    }
+
+    /// Returns the rest of the decompressed data once all input was handed over.
+    ///
+    /// Fails if the input ended in the middle of the compressed data.
+    pub(crate) fn finish(&mut self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip(d) => d.try_finish()?,
+            Self::Xz(d) => return d.finish(),
+            Self::Zstd(d) => d.finish()?,
+        }
+        Ok(std::mem::take(self.output()))
+    }
+}
+
+/// Decompressing is not supported without the `decompress` feature.
+#[cfg(not(feature = "decompress"))]
+pub(crate) struct Decoder(std::convert::Infallible);
+
+#[cfg(not(feature = "decompress"))]
+impl Decoder {
+    pub(crate) fn new(format: Format) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("Can not decompress {format} data: The \"decompress\" feature is disabled."),
+        ))
+    }
+
+    pub(crate) const fn update(&self, _input: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self.0 {}
+    }
+
+    pub(crate) const fn finish(&self) -> std::io::Result<Vec<u8>> {
+        match self.0 {}
+    }
+}
+
+/// Decompress the file at `from` in `format` into a new file at `to`.
+///
+/// `progress` is called with the number of compressed bytes read so far.
+#[cfg(feature = "decompress")]
+pub(crate) fn decompress(
+    format: Format,
+    from: &std::path::Path,
+    to: &std::path::Path,
+    progress: &crate::SimpleProgress,
+) -> std::io::Result<()> {
+    let input = std::io::BufReader::new(Counter::new(std::fs::File::open(from)?, progress));
+    let mut output = std::io::BufWriter::new(std::fs::File::create(to)?);
+    std::io::copy(&mut decoder(format, input)?, &mut output)?;
+    std::io::Write::flush(&mut output)
+}
+
+/// Decompressing is not supported without the `decompress` feature.
+#[cfg(not(feature = "decompress"))]
+pub(crate) fn decompress(
+    format: Format,
+    _from: &std::path::Path,
+    _to: &std::path::Path,
+    _progress: &crate::SimpleProgress,
+) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("Can not decompress {format} data: The \"decompress\" feature is disabled."),
+    ))
+}
+
+#[cfg(all(test, feature = "decompress"))]
+mod tests {
+    use super::*;
+
+    fn compress(format: Format, data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        match format {
+            Format::Gzip => {
+                let mut e =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Format::Xz => {
+                let mut e = xz2::write::XzEncoder::new(Vec::new(), 6);
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Format::Zstd => zstd::stream::encode_all(data, 0).unwrap(),
+        }
+    }
+
+    fn data() -> Vec<u8> {
+        (0..200_000_u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect()
+    }
+
+    fn decode(format: Format, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut decoder = Decoder::new(format)?;
+        let mut output = Vec::new();
+        for chunk in input.chunks(1000) {
+            output.extend(decoder.update(chunk)?);
+        }
+        output.extend(decoder.finish()?);
+        Ok(output)
+    }
+
+    #[test]
+    fn decoder_decompresses_in_pieces() {
+        let data = data();
+        for format in [Format::Gzip, Format::Xz, Format::Zstd] {
+            let compressed = compress(format, &data);
+            assert_eq!(decode(format, &compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn decoder_handles_concatenated_streams() {
+        let data = data();
+        for format in [Format::Gzip, Format::Xz, Format::Zstd] {
+            let mut compressed = compress(format, &data);
+            compressed.extend(compress(format, &data));
+            let decoded = decode(format, &compressed).unwrap();
+            assert_eq!(decoded.len(), 2 * data.len());
+        }
+    }
+
+    #[test]
+    fn decoder_detects_truncated_input() {
+        let data = data();
+        for format in [Format::Gzip, Format::Xz, Format::Zstd] {
+            let compressed = compress(format, &data);
+            let truncated = &compressed[..compressed.len() - 10];
+            assert!(decode(format, truncated).is_err());
+        }
+    }
+}
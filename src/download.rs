@@ -4,6 +4,139 @@
 //! The `Download` struct is used to describe a file that is
 //! supposed to get downloaded.
 
+// ----------------------------------------------------------------------
+// - PartialFile:
+// ----------------------------------------------------------------------
+
+/// What to do with the partially downloaded file when a `Download` fails
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PartialFile {
+    /// Remove the partial file.
+    Remove,
+    /// Keep the partial file, so that a later run can `resume` from it.
+    Keep,
+}
+
+// ----------------------------------------------------------------------
+// - ExistingFile:
+// ----------------------------------------------------------------------
+
+/// What to do when the file to download exists already
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExistingFile {
+    /// Fail with `Error::FileExists`.
+    Fail,
+    /// Download again and replace the existing file.
+    Overwrite,
+    /// Do not download at all.
+    Skip,
+    /// Do not download if the existing file passes the `verify_callback`,
+    /// replace it otherwise.
+    SkipIfVerified,
+    /// Download into a new file named `name (1).ext`, `name (2).ext`, ...
+    Rename,
+    /// Ask the server to only send the file if it changed since the last
+    /// download and replace the existing file if it did.
+    ///
+    /// The `ETag` and `Last-Modified` headers of the response are stored in a
+    /// `<file_name>.meta` sidecar file (see `metadata::Metadata`) to send
+    /// `If-None-Match` and `If-Modified-Since` with. Without a sidecar file
+    /// this works like `Overwrite`.
+    Update,
+}
+
+// ----------------------------------------------------------------------
+// - Naming:
+// ----------------------------------------------------------------------
+
+/// How to name the downloaded file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Naming {
+    /// Use `file_name`.
+    Fixed,
+    /// Use the name suggested by the server in the `Content-Disposition` header,
+    /// falling back to the last segment of the URL after all redirects. A file
+    /// extension matching the `Content-Type` is added to names without one.
+    Response,
+}
+
+// ----------------------------------------------------------------------
+// - Auth:
+// ----------------------------------------------------------------------
+
+/// How to authenticate with the server
+#[derive(Clone, Debug)]
+pub enum Auth {
+    /// HTTP basic authentication.
+    Basic {
+        /// The user name.
+        user: String,
+        /// The password, if any.
+        password: Option<String>,
+    },
+    /// A bearer token.
+    Bearer(String),
+}
+
+// ----------------------------------------------------------------------
+// - Sink:
+// ----------------------------------------------------------------------
+
+/// A writer a `Download` can be written into
+///
+/// Keep a clone around to get hold of the writer again once the download is done.
+pub type Writer = std::sync::Arc<tokio::sync::Mutex<dyn tokio::io::AsyncWrite + Send + Unpin>>;
+
+/// Where the data of a `Download` ends up
+///
+/// `Sink::File` downloads into a `.part` file that is moved into place once it
+/// was downloaded completely and got verified. The other sinks get the data
+/// while it is received, unless a `.part` file is needed for the `verify`
+/// callback, `segments`, `extract`, `resume` or `PartialFile::Keep`. Data
+/// written into a `Sink::Writer` can not be taken back, so the writer may have
+/// received data of a download that fails later on (e.g. in the
+/// `verify_streaming` verifier).
+#[derive(Clone)]
+pub enum Sink {
+    /// Move the data into the file at `file_name`.
+    File,
+    /// Return the data in `DownloadSummary::data`.
+    Memory,
+    /// Write the data into a `Writer`.
+    Writer(Writer),
+}
+
+/// The action taken for a `Download`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileAction {
+    /// The file was downloaded.
+    Downloaded,
+    /// The file was downloaded, replacing an existing file.
+    Overwritten,
+    /// The file existed already and was not downloaded.
+    Skipped,
+    /// The file existed already and was downloaded into a new file.
+    Renamed,
+    /// The file existed already and did not change on the server.
+    Unchanged,
+}
+
+impl std::fmt::Display for FileAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match &self {
+                Self::Downloaded => "downloaded",
+                Self::Overwritten => "overwritten",
+                Self::Skipped => "skipped",
+                Self::Renamed => "renamed",
+                Self::Unchanged => "unchanged",
+            }
+        )
+    }
+}
+
 // ----------------------------------------------------------------------
 // - Download:
 // ----------------------------------------------------------------------
@@ -11,8 +144,12 @@
 /// A `Download`.
 pub struct Download {
     /// A list of URLs that this file can be retrieved from. `downloader` will pick
-    /// the download URL from this list at random.
+    /// the download URL from this list according to `mirror_order`.
     pub urls: Vec<String>,
+    /// How to pick the next URL out of `urls`.
+    pub mirror_order: crate::mirror::Order,
+    /// The weights of the `urls`, used with `mirror::Order::Weighted`.
+    pub mirror_weights: Vec<u32>,
     /// A progress `Reporter` to report the download process with.
     pub progress: Option<crate::Progress>,
     /// The file name to be used for the downloaded file.
@@ -23,6 +160,56 @@ pub struct Download {
     pub output_path: Option<std::path::PathBuf>,
     /// A callback used to verify the download with.
     pub verify_callback: crate::Verify,
+    /// A verifier fed with the data while it is received.
+    pub streaming_verify: Option<crate::StreamingVerify>,
+    /// If set to `true`, an existing partial file is treated as the beginning
+    /// of the download and only the missing part is requested.
+    pub resume: bool,
+    /// The folder to download into before the file is moved to its final
+    /// location. Defaults to the folder of the final file when unset.
+    pub temp_dir: Option<std::path::PathBuf>,
+    /// What to do with the partial file if the download fails.
+    pub partial: PartialFile,
+    /// What to do if the file exists already.
+    pub existing: ExistingFile,
+    /// The retry policy to use instead of the one of the `Downloader`.
+    pub retry_policy: Option<crate::retry::Policy>,
+    /// The number of connections to download this file with. Values larger
+    /// than 1 split the file into segments that are downloaded in parallel.
+    pub segments: u16,
+    /// If set to `true`, segments are spread over all `urls` instead of
+    /// picking a URL at random for each of them.
+    pub spread_segments: bool,
+    /// Where the downloaded data ends up.
+    pub sink: Sink,
+    /// How to name the downloaded file.
+    pub naming: Naming,
+    /// The HTTP method to use.
+    pub method: reqwest::Method,
+    /// Extra headers sent with every request.
+    pub headers: reqwest::header::HeaderMap,
+    /// Extra headers sent with requests to one of the `urls`, replacing those
+    /// in `headers` with the same name.
+    pub mirror_headers: std::collections::HashMap<String, reqwest::header::HeaderMap>,
+    /// How to authenticate with the server.
+    pub auth: Option<Auth>,
+    /// The body sent with every request.
+    pub body: Option<Vec<u8>>,
+    /// A token to cancel just this download with.
+    pub cancel: Option<crate::cancel::Token>,
+    /// Limits the bandwidth of this download, on top of the limit of the `Downloader`.
+    pub bandwidth: Option<crate::bandwidth::Limiter>,
+    /// If set, overrides whether the `Downloader` sets the modification time
+    /// of the file from the `Last-Modified` header.
+    pub preserve_mtime: Option<bool>,
+    /// When to decompress the downloaded data.
+    pub decompress: crate::decompress::Decompression,
+    /// Whether to verify the data before or after decompressing it.
+    pub verify_target: crate::decompress::VerifyTarget,
+    /// Where and how to extract the downloaded archive.
+    pub extract: Option<crate::extract::Extraction>,
+    /// Hooks to run once the file landed, in order.
+    pub hooks: Vec<crate::hook::Hook>,
 }
 
 fn file_name_from_url(url: &str) -> std::path::PathBuf {
@@ -34,23 +221,196 @@ fn file_name_from_url(url: &str) -> std::path::PathBuf {
     };
 
     url.path_segments()
-        .map_or_else(std::path::PathBuf::new, |f| {
-            std::path::PathBuf::from(f.last().unwrap_or(""))
+        .map_or_else(std::path::PathBuf::new, |mut f| {
+            std::path::PathBuf::from(f.next_back().unwrap_or(""))
+        })
+}
+
+/// Decode `%XX` escapes in `value`.
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        if let Some(b) = escaped {
+            result.push(b);
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    result
+}
+
+/// Read a quoted string (without the opening quote) up to its closing quote.
+///
+/// Returns the unescaped string and the rest of `quoted` after the closing quote.
+fn unquote(quoted: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            '"' => return (value, &quoted[i + 1..]),
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+/// Split the parameters of a `Content-Disposition` header into names and
+/// (unquoted) values.
+fn disposition_parameters(value: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut rest = value.split_once(';').map_or("", |(_, p)| p);
+    while let Some((name, tail)) = rest.split_once('=') {
+        let tail = tail.trim_start();
+        let (value, tail) = tail.strip_prefix('"').map_or_else(
+            || {
+                let (value, tail) = tail.split_once(';').unwrap_or((tail, ""));
+                (value.trim().to_owned(), tail)
+            },
+            |quoted| {
+                let (value, tail) = unquote(quoted);
+                (value, tail.split_once(';').map_or("", |(_, t)| t))
+            },
+        );
+        result.push((name.trim().to_ascii_lowercase(), value));
+        rest = tail;
+    }
+    result
+}
+
+/// Find the file name in a `Content-Disposition` header, preferring an
+/// RFC 5987 encoded `filename*` over a plain `filename`.
+fn file_name_from_disposition(value: &str) -> Option<String> {
+    let parameters = disposition_parameters(value);
+    let extended = parameters
+        .iter()
+        .find(|(n, _)| n == "filename*")
+        .and_then(|(_, v)| {
+            let (charset, rest) = v.split_once('\'')?;
+            let (_, encoded) = rest.split_once('\'')?;
+            let bytes = percent_decode(encoded);
+            if charset.eq_ignore_ascii_case("utf-8") {
+                String::from_utf8(bytes).ok()
+            } else {
+                // ISO-8859-1 maps directly onto the first unicode code points:
+                Some(bytes.into_iter().map(char::from).collect())
+            }
+        });
+    extended.or_else(|| {
+        parameters
+            .into_iter()
+            .find(|(n, _)| n == "filename")
+            .map(|(_, v)| v)
+    })
+}
+
+/// A file extension matching the `content_type`.
+fn extension_for(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    Some(match mime.as_str() {
+        "application/json" => "json",
+        "application/xml" | "text/xml" => "xml",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "application/gzip" | "application/x-gzip" => "gz",
+        "application/x-tar" => "tar",
+        "application/x-xz" => "xz",
+        "application/zstd" => "zst",
+        "text/plain" => "txt",
+        "text/html" => "html",
+        "text/csv" => "csv",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        _ => return None,
+    })
+}
+
+/// Make `name` safe to use as a file name: Drop everything up to the last path
+/// separator, replace reserved and control characters and avoid names that are
+/// special on some platforms.
+fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "<>:\"|?*".contains(c) {
+                '_'
+            } else {
+                c
+            }
         })
+        .collect();
+    let name = name
+        .trim_start_matches(['.', ' '])
+        .trim_end_matches(['.', ' ']);
+
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.len() == 4
+            && stem.as_bytes()[3].is_ascii_digit());
+    let mut name = if reserved {
+        format!("_{name}")
+    } else {
+        name.to_owned()
+    };
+
+    while name.len() > 255 {
+        name.pop();
+    }
+    name
+}
+
+/// Find a file name for a download named via `Naming::Response`.
+pub(crate) fn file_name_from_response(
+    url: &str,
+    content_disposition: Option<&str>,
+    content_type: Option<&str>,
+) -> std::path::PathBuf {
+    let suggested = content_disposition
+        .and_then(file_name_from_disposition)
+        .map(|n| sanitize_file_name(&n))
+        .filter(|n| !n.is_empty());
+    let name = suggested.unwrap_or_else(|| {
+        let name = file_name_from_url(url);
+        let name = String::from_utf8_lossy(&percent_decode(&name.to_string_lossy())).into_owned();
+        let name = sanitize_file_name(&name);
+        if name.is_empty() {
+            String::from("download")
+        } else {
+            name
+        }
+    });
+
+    let mut name = std::path::PathBuf::from(name);
+    if name.extension().is_none() {
+        if let Some(extension) = content_type.and_then(extension_for) {
+            name.set_extension(extension);
+        }
+    }
+    name
 }
 
 impl Download {
     /// Create a new `Download` with a single download `url`
     #[must_use]
     pub fn new(url: &str) -> Self {
-        Self {
-            urls: vec![url.to_owned()],
-            progress: None,
-            file_name: file_name_from_url(url),
-            check_file_name: true,
-            output_path: None,
-            verify_callback: crate::verify::noop(),
-        }
+        Self::new_mirrored(&[url])
     }
 
     /// Create a new `Download` with a single download
@@ -60,12 +420,8 @@ impl Download {
     #[must_use]
     pub fn new_with_output<P: AsRef<std::path::Path>>(url: &str, output_path: P) -> Self {
         Self {
-            urls: vec![url.to_owned()],
-            progress: None,
-            file_name: file_name_from_url(url),
-            check_file_name: true,
             output_path: Some(output_path.as_ref().to_path_buf()),
-            verify_callback: crate::verify::noop(),
+            ..Self::new_mirrored(&[url])
         }
     }
 
@@ -73,25 +429,76 @@ impl Download {
     #[must_use]
     pub fn new_mirrored(urls: &[&str]) -> Self {
         let urls: Vec<String> = urls.iter().map(|s| String::from(*s)).collect();
-        let url = urls.get(0).unwrap_or(&String::new()).clone();
+        let url = urls.first().unwrap_or(&String::new()).clone();
 
         Self {
             urls,
+            mirror_order: crate::mirror::Order::Random,
+            mirror_weights: Vec::new(),
             progress: None,
             file_name: file_name_from_url(&url),
             check_file_name: true,
             output_path: None,
             verify_callback: crate::verify::noop(),
+            streaming_verify: None,
+            resume: false,
+            temp_dir: None,
+            partial: PartialFile::Remove,
+            existing: ExistingFile::Fail,
+            retry_policy: None,
+            segments: 1,
+            spread_segments: false,
+            sink: Sink::File,
+            naming: Naming::Fixed,
+            method: reqwest::Method::GET,
+            headers: reqwest::header::HeaderMap::new(),
+            mirror_headers: std::collections::HashMap::new(),
+            auth: None,
+            body: None,
+            cancel: None,
+            bandwidth: None,
+            preserve_mtime: None,
+            decompress: crate::decompress::Decompression::Off,
+            verify_target: crate::decompress::VerifyTarget::Decompressed,
+            extract: None,
+            hooks: Vec::new(),
         }
     }
 
+    /// Create a new `Download` based on a list of mirror urls with weights.
+    ///
+    /// Mirrors with higher weights are picked more often.
+    #[must_use]
+    pub fn new_weighted(urls: &[(&str, u32)]) -> Self {
+        let (mirrors, weights): (Vec<&str>, Vec<u32>) = urls.iter().copied().unzip();
+        let mut result = Self::new_mirrored(&mirrors);
+        result.mirror_order = crate::mirror::Order::Weighted;
+        result.mirror_weights = weights;
+        result
+    }
+
+    /// Set how to pick the next mirror to try
+    ///
+    /// Every mirror is tried once before any mirror is used again and mirrors that
+    /// keep failing are avoided in all downloads of a `Downloader`. Mirrors giving
+    /// an answer that is not worth retrying (like `404 Not Found`) are not used
+    /// again for this download.
+    ///
+    /// Default is `mirror::Order::Random`, or `mirror::Order::Weighted` for
+    /// downloads created with `new_weighted`.
+    #[must_use]
+    pub const fn mirror_order(mut self, order: crate::mirror::Order) -> Self {
+        self.mirror_order = order;
+        self
+    }
+
     /// Set the name of the downloaded file. This filename can be absolute or
     /// relative to the `download_folder` defined in the `Downloader`.
     ///
     /// Default is the file name on the server side (if available)
     #[must_use]
     pub fn file_name(mut self, path: &std::path::Path) -> Self {
-        self.file_name = path.to_owned();
+        path.clone_into(&mut self.file_name);
         self
     }
 
@@ -104,6 +511,262 @@ impl Download {
         self
     }
 
+    /// Continue a download left over from an earlier run
+    ///
+    /// The data already found in the partial file (see `temp_dir`) is kept and only
+    /// the rest is requested from the server. The `ETag` or `Last-Modified` value
+    /// the data came with is stored in a `.meta` file next to the partial file
+    /// and sent along, so the data is only kept if the file did not change on the
+    /// server. If there is no such value or the server does not support `Range`
+    /// requests, the file is downloaded from the start again.
+    ///
+    /// Defaults to `false`, starting over every time. Interrupted transfers within
+    /// one run are always continued where possible.
+    #[must_use]
+    pub const fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Set the folder to download into
+    ///
+    /// Files are downloaded into a `.part` file first, which is moved to the final
+    /// location once the download was successful and got verified. A relative
+    /// `path` is relative to the `download_folder` defined in the `Downloader`.
+    ///
+    /// Default is to put the `.part` file next to the final file.
+    #[must_use]
+    pub fn temp_dir(mut self, path: &std::path::Path) -> Self {
+        self.temp_dir = Some(path.to_path_buf());
+        self
+    }
+
+    /// Set what happens to the partial file if the download fails
+    ///
+    /// The partial file of a download in `segments` is always removed: It has
+    /// gaps where segments are missing, so it can not be resumed.
+    ///
+    /// Default is `PartialFile::Remove`.
+    #[must_use]
+    pub const fn partial(mut self, partial: PartialFile) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    /// Set what happens if the file exists already
+    ///
+    /// Default is `ExistingFile::Fail`.
+    #[must_use]
+    pub const fn existing(mut self, existing: ExistingFile) -> Self {
+        self.existing = existing;
+        self
+    }
+
+    /// Set the policy deciding when and how fast to retry this download
+    ///
+    /// Default is the retry policy set in the `Downloader`.
+    #[must_use]
+    pub fn retry_policy(mut self, policy: crate::retry::Policy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Download the file in `count` segments over parallel connections
+    ///
+    /// This only happens when the server reports the file size and supports
+    /// `Range` requests, the file is large enough and no partial file is resumed.
+    /// Otherwise the file is downloaded over one connection. Each segment counts
+    /// against the `parallel_requests` of the `Downloader`.
+    ///
+    /// Defaults to 1.
+    #[must_use]
+    pub const fn segments(mut self, count: u16) -> Self {
+        self.segments = count;
+        self
+    }
+
+    /// Spread segments over all mirror URLs
+    ///
+    /// Defaults to `false`, which picks a random URL for each segment.
+    #[must_use]
+    pub const fn spread_segments(mut self, spread: bool) -> Self {
+        self.spread_segments = spread;
+        self
+    }
+
+    /// Set where the downloaded data ends up
+    ///
+    /// A `file_name` is not required for sinks other than `Sink::File`. If they
+    /// need a `.part` file, it goes into the `temp_dir` (or the `download_folder`
+    /// of the `Downloader`) and gets a name derived from the URLs. `existing` is
+    /// ignored for them.
+    ///
+    /// Default is `Sink::File`.
+    #[must_use]
+    pub fn sink(mut self, sink: Sink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    /// Set how to name the downloaded file
+    ///
+    /// With `Naming::Response`, the file is put into the `output_path` (or the
+    /// `download_folder` of the `Downloader`) under the name the server response
+    /// suggests, so that no `file_name` is required. The `.part` file goes into
+    /// the `temp_dir` (or that folder) and gets a name derived from the URLs.
+    /// `existing` is applied once the name is known, after the download.
+    ///
+    /// Default is `Naming::Fixed`.
+    #[must_use]
+    pub const fn naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Set the HTTP method to use
+    ///
+    /// Downloads using other methods than `GET` are never split into `segments`.
+    ///
+    /// Default is `GET`.
+    #[must_use]
+    pub fn method(mut self, method: reqwest::Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Add a header to send with every request
+    #[must_use]
+    pub fn header(
+        mut self,
+        name: reqwest::header::HeaderName,
+        value: reqwest::header::HeaderValue,
+    ) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Add a header to send with requests to the mirror at `url` only
+    ///
+    /// This replaces a header with the same `name` set via `header`, e.g. to use
+    /// different credentials for one mirror.
+    #[must_use]
+    pub fn mirror_header(
+        mut self,
+        url: &str,
+        name: reqwest::header::HeaderName,
+        value: reqwest::header::HeaderValue,
+    ) -> Self {
+        self.mirror_headers
+            .entry(url.to_owned())
+            .or_default()
+            .insert(name, value);
+        self
+    }
+
+    /// Use HTTP basic authentication
+    #[must_use]
+    pub fn basic_auth(mut self, user: &str, password: Option<&str>) -> Self {
+        self.auth = Some(Auth::Basic {
+            user: user.to_owned(),
+            password: password.map(std::borrow::ToOwned::to_owned),
+        });
+        self
+    }
+
+    /// Authenticate with a bearer `token`
+    #[must_use]
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.auth = Some(Auth::Bearer(token.to_owned()));
+        self
+    }
+
+    /// Set the body to send with every request
+    ///
+    /// Default is to send no body.
+    #[must_use]
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Register a token to cancel this download with
+    ///
+    /// The same token can be handed to several downloads to cancel them together.
+    /// The `Downloader` has a token of its own that cancels all of its downloads.
+    #[must_use]
+    pub fn cancel_token(mut self, token: crate::cancel::Token) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Limit the bandwidth of this download
+    ///
+    /// The same `limiter` can be handed to several downloads to limit their
+    /// combined bandwidth. Its rate can be changed while downloads are running.
+    ///
+    /// Default is to only apply the limit of the `Downloader`.
+    #[must_use]
+    pub fn bandwidth_limiter(mut self, limiter: crate::bandwidth::Limiter) -> Self {
+        self.bandwidth = Some(limiter);
+        self
+    }
+
+    /// Set the modification time of the downloaded file from the
+    /// `Last-Modified` header of the response
+    ///
+    /// Files that are not downloaded (e.g. skipped or unchanged) keep their
+    /// modification time.
+    ///
+    /// Default is the setting of the `Downloader`.
+    #[must_use]
+    pub const fn preserve_mtime(mut self, preserve: bool) -> Self {
+        self.preserve_mtime = Some(preserve);
+        self
+    }
+
+    /// Set when to decompress the downloaded data
+    ///
+    /// The data is decompressed on the fly, while it is received, with progress
+    /// reported in compressed bytes. Decompressed data can not be continued in
+    /// a later run, so `resume` has no effect. Segmented downloads and those
+    /// with a `verify` callback checking the compressed data are decompressed
+    /// once they are complete instead. This needs the `decompress` feature.
+    ///
+    /// Default is `Decompression::Off`.
+    #[must_use]
+    pub const fn decompress(mut self, decompress: crate::decompress::Decompression) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Set whether the `verify_callback` checks the compressed or the
+    /// decompressed data
+    ///
+    /// This only matters for downloads that get decompressed. Note that
+    /// `ExistingFile::SkipIfVerified` always checks the existing (decompressed)
+    /// file.
+    ///
+    /// Default is `VerifyTarget::Decompressed`.
+    #[must_use]
+    pub const fn verify_target(mut self, target: crate::decompress::VerifyTarget) -> Self {
+        self.verify_target = target;
+        self
+    }
+
+    /// Extract the downloaded archive
+    ///
+    /// The archive is extracted once it passed the `verify_callback` and got
+    /// decompressed (if requested), before it is handed to the sink. Entries
+    /// that would end up outside of the target directory fail the download.
+    /// This needs the `extract` feature.
+    ///
+    /// Default is to not extract anything.
+    #[must_use]
+    pub fn extract(mut self, extraction: crate::extract::Extraction) -> Self {
+        self.extract = Some(extraction);
+        self
+    }
+
     /// Register a callback to verify a download
     ///
     /// Default is to assume the file was downloaded correctly.
@@ -112,4 +775,141 @@ impl Download {
         self.verify_callback = func;
         self
     }
+
+    /// Register a verifier that is fed the data while it is received
+    ///
+    /// This saves reading the file again after downloading it. Data that is
+    /// not received in order (e.g. in `segments`) is read from disk instead.
+    /// For decompressed downloads, the verifier gets to see the data the
+    /// `verify_target` names. Resumed downloads feed
+    /// the existing part of the file first. Both this and the `verify`
+    /// callback need to pass if both are set.
+    ///
+    /// Default is to not verify while downloading.
+    #[must_use]
+    pub fn verify_streaming(mut self, verifier: crate::StreamingVerify) -> Self {
+        self.streaming_verify = Some(verifier);
+        self
+    }
+
+    /// Verify the download against a `checksum` while it is received
+    ///
+    /// The `checksum` is anything `verify::Checksum::parse` understands, e.g.
+    /// `sha256:<hex>` or `sha384-<base64>`. This replaces any verifier
+    /// registered with `verify_streaming`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the `checksum` is malformed.
+    #[cfg(feature = "verify")]
+    pub fn checksum(self, checksum: &str) -> crate::Result<Self> {
+        let checksum: crate::verify::Checksum = checksum.parse()?;
+        Ok(self.verify_streaming(checksum.streaming()))
+    }
+
+    /// Add a hook to run once the file was downloaded, verified and handed to
+    /// the sink
+    ///
+    /// Hooks run in the order they were added. Default is to run no hooks.
+    #[must_use]
+    pub fn hook(mut self, hook: crate::hook::Hook) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disposition_plain_file_name() {
+        assert_eq!(
+            file_name_from_disposition("attachment; filename=\"report.pdf\"").as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(
+            file_name_from_disposition("attachment; filename=report.pdf").as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(file_name_from_disposition("inline"), None);
+    }
+
+    #[test]
+    fn disposition_extended_file_name() {
+        assert_eq!(
+            file_name_from_disposition("attachment; filename*=UTF-8''%e2%82%ac%20rates.txt")
+                .as_deref(),
+            Some("\u{20ac} rates.txt")
+        );
+        assert_eq!(
+            file_name_from_disposition("attachment; filename*=iso-8859-1'en'%A3%20rates.txt")
+                .as_deref(),
+            Some("\u{a3} rates.txt")
+        );
+    }
+
+    #[test]
+    fn disposition_prefers_extended_file_name() {
+        assert_eq!(
+            file_name_from_disposition(
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''better.txt"
+            )
+            .as_deref(),
+            Some("better.txt")
+        );
+        assert_eq!(
+            file_name_from_disposition(
+                "attachment; filename*=UTF-8''better.txt; filename=\"fallback.txt\""
+            )
+            .as_deref(),
+            Some("better.txt")
+        );
+    }
+
+    #[test]
+    fn sanitize_strips_paths() {
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("..\\..\\boot.ini"), "boot.ini");
+        assert_eq!(sanitize_file_name(".."), "");
+        assert_eq!(sanitize_file_name("..."), "");
+        assert_eq!(sanitize_file_name(" .hidden. "), "hidden");
+    }
+
+    #[test]
+    fn sanitize_replaces_reserved_characters() {
+        assert_eq!(sanitize_file_name("a<b>c:d\"e|f?g*h"), "a_b_c_d_e_f_g_h");
+        assert_eq!(sanitize_file_name("tab\there"), "tab_here");
+    }
+
+    #[test]
+    fn sanitize_avoids_reserved_names() {
+        assert_eq!(sanitize_file_name("CON"), "_CON");
+        assert_eq!(sanitize_file_name("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_file_name("com1.log"), "_com1.log");
+        assert_eq!(sanitize_file_name("LPT9"), "_LPT9");
+        assert_eq!(sanitize_file_name("console.txt"), "console.txt");
+        assert_eq!(sanitize_file_name("COM10"), "COM10");
+    }
+
+    #[test]
+    fn sanitize_limits_length() {
+        assert_eq!(sanitize_file_name(&"x".repeat(300)).len(), 255);
+    }
+
+    #[test]
+    fn response_falls_back_to_url() {
+        assert_eq!(
+            file_name_from_response(
+                "https://example.com/a/b.tar.gz?x=1",
+                Some("attachment; filename=\"..\""),
+                None
+            ),
+            std::path::PathBuf::from("b.tar.gz")
+        );
+        assert_eq!(
+            file_name_from_response("https://example.com/", None, Some("application/json")),
+            std::path::PathBuf::from("download.json")
+        );
+    }
 }
@@ -39,47 +39,89 @@ fn validate_downloads(
         }
 
         let urls = d.urls.clone();
+        // Is the file name needed before downloading?
+        let to_file = matches!(d.sink, crate::download::Sink::File)
+            && d.naming == crate::download::Naming::Fixed;
 
-        if d.file_name.to_string_lossy().is_empty() {
+        if to_file && d.file_name.to_string_lossy().is_empty() {
             return Err(Error::DownloadDefinition(String::from(
                 "No download file name was provided.",
             )));
         }
 
-        let file_name = if let Some(output_path) = &d.output_path {
-            output_path.join(&d.file_name)
+        let file_name = if d.file_name.to_string_lossy().is_empty() {
+            std::path::PathBuf::new()
         } else {
-            download_folder.join(&d.file_name)
+            d.output_path.as_ref().map_or_else(
+                || download_folder.join(&d.file_name),
+                |output_path| output_path.join(&d.file_name),
+            )
         };
 
-        if d.file_name.to_string_lossy().is_empty() {
+        if to_file && d.file_name.to_string_lossy().is_empty() {
             return Err(Error::DownloadDefinition(String::from(
                 "Failed to get full download path.",
             )));
         }
 
-        if d.check_file_name {
-            if !known_download_paths.insert(&d.file_name) {
-                return Err(Error::DownloadDefinition(format!(
-                    "Download file name \"{}\" is used more than once.",
-                    d.file_name.to_string_lossy(),
-                )));
-            }
+        if to_file && d.check_file_name && !known_download_paths.insert(&d.file_name) {
+            return Err(Error::DownloadDefinition(format!(
+                "Download file name \"{}\" is used more than once.",
+                d.file_name.to_string_lossy(),
+            )));
         }
 
-        let progress = if d.progress.is_none() {
-            factory.create_reporter()
-        } else {
-            d.progress.as_ref().expect("Was Some just now...").clone()
-        };
+        let progress = d
+            .progress
+            .as_ref()
+            .map_or_else(|| factory.create_reporter(), std::clone::Clone::clone);
 
         result.push(Download {
             urls,
             file_name,
             progress: Some(progress),
             check_file_name: false,
-            output_path: d.output_path.clone(),
+            output_path: if d.naming == crate::download::Naming::Response {
+                Some(
+                    d.output_path
+                        .clone()
+                        .unwrap_or_else(|| download_folder.to_path_buf()),
+                )
+            } else {
+                d.output_path.clone()
+            },
             verify_callback: d.verify_callback.clone(),
+            streaming_verify: d.streaming_verify.clone(),
+            resume: d.resume,
+            temp_dir: d
+                .temp_dir
+                .as_ref()
+                .map(|t| download_folder.join(t))
+                .or_else(|| (!to_file).then(|| download_folder.to_path_buf())),
+            partial: d.partial,
+            existing: d.existing,
+            retry_policy: d.retry_policy.clone(),
+            mirror_order: d.mirror_order,
+            mirror_weights: d.mirror_weights.clone(),
+            segments: d.segments,
+            spread_segments: d.spread_segments,
+            sink: d.sink.clone(),
+            naming: d.naming,
+            method: d.method.clone(),
+            headers: d.headers.clone(),
+            mirror_headers: d.mirror_headers.clone(),
+            auth: d.auth.clone(),
+            body: d.body.clone(),
+            cancel: d.cancel.clone(),
+            bandwidth: d.bandwidth.clone(),
+            preserve_mtime: d.preserve_mtime,
+            decompress: d.decompress,
+            verify_target: d.verify_target,
+            extract: d.extract.clone().map(|mut e| {
+                e.directory = download_folder.join(&e.directory);
+                e
+            }),
+            hooks: d.hooks.clone(),
         });
     }
 
@@ -95,7 +137,19 @@ fn validate_downloads(
 pub struct Downloader {
     client: reqwest::Client,
     parallel_requests: u16,
+    connections_per_host: Option<u16>,
+    host_connections: std::collections::HashMap<String, u16>,
     retries: u16,
+    retry_policy: crate::retry::Policy,
+    health: std::sync::Arc<crate::mirror::Health>,
+    cancel: crate::cancel::Token,
+    bandwidth: crate::bandwidth::Limiter,
+    events: crate::event::Subscribers,
+    /// The runtime to run blocking downloads on.
+    runtime: Option<tokio::runtime::Handle>,
+    /// The runtime created by this `Downloader` if none was provided.
+    own_runtime: Option<tokio::runtime::Runtime>,
+    preserve_mtime: bool,
     download_folder: std::path::PathBuf,
 }
 
@@ -106,10 +160,79 @@ impl Downloader {
         Builder::default()
     }
 
+    /// Health information on all hosts contacted by this `Downloader` so far,
+    /// indexed by `host:port`.
+    #[must_use]
+    pub fn host_health(&self) -> std::collections::HashMap<String, crate::mirror::HostStats> {
+        self.health.stats()
+    }
+
+    /// The token that cancels all downloads of this `Downloader`
+    ///
+    /// Cancelling it aborts the running batch. The token is reset when the next
+    /// batch starts, so later calls to `download` or `async_download` run normally.
+    #[must_use]
+    pub fn cancel_token(&self) -> crate::cancel::Token {
+        self.cancel.clone()
+    }
+
+    /// The limiter for the combined bandwidth of all downloads of this `Downloader`
+    ///
+    /// Use this to change the limit, even while downloads are running.
+    #[must_use]
+    pub fn bandwidth_limiter(&self) -> crate::bandwidth::Limiter {
+        self.bandwidth.clone()
+    }
+
+    /// Subscribe to the events of all downloads of this `Downloader`
+    ///
+    /// Events are buffered until they are read, so keep reading the returned
+    /// stream or drop it.
+    #[must_use]
+    pub fn events(&self) -> crate::event::Events {
+        self.events.subscribe()
+    }
+
+    /// The runtime to run downloads on, creating one on first use if none was
+    /// provided.
+    fn runtime(&mut self) -> Result<tokio::runtime::Handle> {
+        if self.runtime.is_none() {
+            let runtime = tokio::runtime::Runtime::new()
+                .map_err(|e| Error::Setup(format!("Failed to set up runtime: {e}")))?;
+            self.runtime = Some(runtime.handle().clone());
+            self.own_runtime = Some(runtime);
+        }
+        Ok(self.runtime.clone().expect("This has been set!"))
+    }
+
+    fn context(&self) -> crate::backend::Context {
+        crate::backend::Context {
+            client: self.client.clone(),
+            retries: self.retries,
+            retry_policy: self.retry_policy.clone(),
+            connections: crate::backend::Connections::new(
+                self.parallel_requests,
+                self.connections_per_host,
+                self.host_connections.clone(),
+            ),
+            health: self.health.clone(),
+            cancel: self.cancel.clone(),
+            bandwidth: self.bandwidth.clone(),
+            events: self.events.clone(),
+            preserve_mtime: self.preserve_mtime,
+        }
+    }
+
     /// Start the download
     ///
+    /// This blocks until all downloads are done. The downloads run on the runtime
+    /// passed to `Builder::runtime`, or on a runtime the `Downloader` creates on
+    /// first use and keeps for later calls. Must not be called from within an
+    /// async context.
+    ///
     /// # Errors
     /// `Error::DownloadDefinition` if the download is detected to be broken in some way.
+    /// `Error::Setup` if no runtime could be created.
     pub fn download(&mut self, downloads: &[Download]) -> Result<Vec<Result<DownloadSummary>>> {
         #[cfg(feature = "tui")]
         let factory = crate::progress::Tui::default();
@@ -121,11 +244,12 @@ impl Downloader {
             return Ok(Vec::new());
         }
 
+        let runtime = self.runtime()?;
+        self.cancel.reset();
         Ok(crate::backend::run(
-            &mut self.client,
+            &runtime,
+            self.context(),
             to_process,
-            self.retries,
-            self.parallel_requests,
             &move || {
                 factory.join();
             },
@@ -134,8 +258,15 @@ impl Downloader {
 
     /// Start the download asyncroniously
     ///
+    /// When awaited within a Tokio runtime, the downloads are driven by the
+    /// returned future itself, so any flavor of Tokio runtime works. Other
+    /// executors are supported, too: The network code needs Tokio, so the
+    /// downloads then run on the runtime used by `download` and the returned
+    /// future just waits for them.
+    ///
     /// # Errors
     /// `Error::DownloadDefinition` if the download is detected to be broken in some way.
+    /// `Error::Setup` if no runtime could be created.
     pub async fn async_download(
         &mut self,
         downloads: &[Download],
@@ -150,21 +281,30 @@ impl Downloader {
             return Ok(Vec::new());
         }
 
-        let result = crate::backend::async_run(
-            &mut self.client,
-            to_process,
-            self.retries,
-            self.parallel_requests,
-            &move || {
-                factory.join();
-            },
-        )
+        let runtime = if tokio::runtime::Handle::try_current().is_ok() {
+            None
+        } else {
+            Some(self.runtime()?)
+        };
+        self.cancel.reset();
+        let result = crate::backend::async_run(runtime, self.context(), to_process, &move || {
+            factory.join();
+        })
         .await;
 
         Ok(result)
     }
 }
 
+impl Drop for Downloader {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics in async contexts:
+        if let Some(runtime) = self.own_runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
 // ----------------------------------------------------------------------
 // - Builder:
 // ----------------------------------------------------------------------
@@ -175,7 +315,14 @@ pub struct Builder {
     connect_timeout: std::time::Duration,
     timeout: std::time::Duration,
     parallel_requests: u16,
+    connections_per_host: Option<u16>,
+    host_connections: std::collections::HashMap<String, u16>,
     retries: u16,
+    retry_policy: crate::retry::Policy,
+    cancel: crate::cancel::Token,
+    bandwidth: Option<u64>,
+    runtime: Option<tokio::runtime::Handle>,
+    preserve_mtime: bool,
     download_folder: std::path::PathBuf,
 }
 
@@ -191,7 +338,7 @@ impl Builder {
     /// Set the connection timeout.
     ///
     /// The default is 30s.
-    pub fn connect_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+    pub const fn connect_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
         self.connect_timeout = timeout;
         self
     }
@@ -199,7 +346,7 @@ impl Builder {
     /// Set the timeout.
     ///
     /// The default is 5min.
-    pub fn timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+    pub const fn timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
         self.timeout = timeout;
         self
     }
@@ -207,19 +354,84 @@ impl Builder {
     /// Set the number of parallel requests.
     ///
     /// The default is 32.
-    pub fn parallel_requests(&mut self, count: u16) -> &mut Self {
+    pub const fn parallel_requests(&mut self, count: u16) -> &mut Self {
         self.parallel_requests = count;
         self
     }
 
+    /// Set the number of parallel requests to any one host.
+    ///
+    /// Downloads from hosts that are at their limit wait, while downloads from
+    /// other hosts go ahead. The `parallel_requests` limit applies on top of this.
+    ///
+    /// The default is no limit.
+    pub const fn connections_per_host(&mut self, count: u16) -> &mut Self {
+        self.connections_per_host = Some(count);
+        self
+    }
+
+    /// Set the number of parallel requests to the mirror at `host`.
+    ///
+    /// `host` is given as `host:port`, like in `Downloader::host_health`. This
+    /// overrides `connections_per_host` for that host.
+    pub fn host_connections(&mut self, host: &str, count: u16) -> &mut Self {
+        self.host_connections.insert(host.to_owned(), count);
+        self
+    }
+
     /// Set the number of retries.
     ///
     /// The default is 3.
-    pub fn retries(&mut self, count: u16) -> &mut Self {
+    pub const fn retries(&mut self, count: u16) -> &mut Self {
         self.retries = count;
         self
     }
 
+    /// Set the policy deciding when and how fast to retry.
+    ///
+    /// `Download`s can override this. The default is `retry::Policy::default()`.
+    pub fn retry_policy(&mut self, policy: crate::retry::Policy) -> &mut Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set the token to cancel all downloads with.
+    ///
+    /// The default is a new token, available via `Downloader::cancel_token`. The
+    /// token is reset whenever the `Downloader` starts a new batch of downloads.
+    pub fn cancel_token(&mut self, token: crate::cancel::Token) -> &mut Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Limit the combined bandwidth of all downloads to `bytes_per_second`.
+    ///
+    /// The limit can be changed later via `Downloader::bandwidth_limiter`.
+    /// The default is no limit.
+    pub const fn bandwidth_limit(&mut self, bytes_per_second: u64) -> &mut Self {
+        self.bandwidth = Some(bytes_per_second);
+        self
+    }
+
+    /// Set the Tokio runtime to run blocking downloads on.
+    ///
+    /// The runtime must be a multi-threaded one or be driven by some other thread.
+    /// The default is to create a runtime when it is first needed.
+    pub fn runtime(&mut self, handle: tokio::runtime::Handle) -> &mut Self {
+        self.runtime = Some(handle);
+        self
+    }
+
+    /// Set the modification time of downloaded files from the `Last-Modified`
+    /// header of the response.
+    ///
+    /// `Download::preserve_mtime` overrides this for single downloads.
+    /// The default is `false`.
+    pub const fn preserve_mtime(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_mtime = preserve;
+        self
+    }
+
     /// Set the folder to download into.
     ///
     /// The default is unset and a value is required.
@@ -262,7 +474,20 @@ impl Builder {
         Ok(Downloader {
             client,
             parallel_requests: self.parallel_requests,
+            connections_per_host: self.connections_per_host,
+            host_connections: self.host_connections.clone(),
             retries: self.retries,
+            retry_policy: self.retry_policy.clone(),
+            health: std::sync::Arc::default(),
+            cancel: self.cancel.clone(),
+            bandwidth: self.bandwidth.map_or_else(
+                crate::bandwidth::Limiter::unlimited,
+                crate::bandwidth::Limiter::new,
+            ),
+            runtime: self.runtime.clone(),
+            own_runtime: None,
+            preserve_mtime: self.preserve_mtime,
+            events: crate::event::Subscribers::default(),
             download_folder: download_folder.clone(),
         })
     }
@@ -294,7 +519,14 @@ impl Default for Builder {
             connect_timeout: std::time::Duration::from_secs(30),
             timeout: std::time::Duration::from_secs(300),
             parallel_requests: 32,
+            connections_per_host: None,
+            host_connections: std::collections::HashMap::new(),
             retries: 3,
+            retry_policy: crate::retry::Policy::default(),
+            cancel: crate::cancel::Token::new(),
+            bandwidth: None,
+            runtime: None,
+            preserve_mtime: false,
             download_folder,
         }
     }
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Events reporting on the lifecycle of downloads
+
+use crate::download::FileAction;
+use crate::Verification;
+
+// ----------------------------------------------------------------------
+// - Event:
+// ----------------------------------------------------------------------
+
+/// Something that happened to a `Download`
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The download is waiting to be started.
+    Queued,
+    /// The download was started.
+    Started,
+    /// A server responded to a request.
+    Connected {
+        /// The URL the request went to.
+        url: String,
+        /// The HTTP status code of the response.
+        status: u16,
+        /// The headers of the response.
+        headers: reqwest::header::HeaderMap,
+        /// The length of the response body, if known.
+        content_length: Option<u64>,
+    },
+    /// Data was received.
+    Received {
+        /// Number of bytes received just now.
+        bytes: u64,
+        /// Number of bytes of the file received so far.
+        total: u64,
+    },
+    /// An attempt failed and will be retried.
+    RetryScheduled {
+        /// The URL of the failed attempt.
+        url: String,
+        /// Why the attempt failed.
+        reason: String,
+        /// How long to wait before the next attempt.
+        delay: std::time::Duration,
+    },
+    /// The next attempt uses another mirror than the last one.
+    MirrorSwitched {
+        /// The URL of the last attempt.
+        from: String,
+        /// The URL of the next attempt.
+        to: String,
+    },
+    /// The file is being verified.
+    VerificationStarted,
+    /// The verification of the file is done.
+    VerificationFinished(Verification),
+    /// The download succeeded.
+    Completed {
+        /// What was done with the file.
+        action: FileAction,
+        /// File verification status
+        verified: Verification,
+    },
+    /// The download failed or was cancelled.
+    Failed {
+        /// The error the download failed with.
+        reason: String,
+    },
+}
+
+/// An `Event` of one `Download`
+#[derive(Clone, Debug)]
+pub struct DownloadEvent {
+    /// The position of the `Download` in the list passed to the `Downloader`.
+    pub index: usize,
+    /// The path the `Download` is downloaded to.
+    pub file_name: std::path::PathBuf,
+    /// What happened.
+    pub event: Event,
+}
+
+/// A `futures::Stream` of `DownloadEvent`s
+pub type Events = futures::channel::mpsc::UnboundedReceiver<DownloadEvent>;
+
+// ----------------------------------------------------------------------
+// - Subscribers:
+// ----------------------------------------------------------------------
+
+/// Everybody interested in the events of a `Downloader`.
+#[derive(Clone, Default)]
+pub(crate) struct Subscribers {
+    senders: std::sync::Arc<
+        std::sync::Mutex<Vec<futures::channel::mpsc::UnboundedSender<DownloadEvent>>>,
+    >,
+}
+
+impl Subscribers {
+    pub(crate) fn subscribe(&self) -> Events {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        self.senders
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(sender);
+        receiver
+    }
+
+    /// Create an `Emitter` for the download at `index`.
+    pub(crate) fn emitter(&self, index: usize, file_name: &std::path::Path) -> Emitter {
+        Emitter {
+            subscribers: self.clone(),
+            index,
+            file_name: file_name.to_path_buf(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Emitter:
+// ----------------------------------------------------------------------
+
+/// Sends the events of one download to all subscribers.
+#[derive(Clone)]
+pub(crate) struct Emitter {
+    subscribers: Subscribers,
+    index: usize,
+    file_name: std::path::PathBuf,
+}
+
+impl Emitter {
+    pub(crate) fn emit(&self, event: Event) {
+        let mut senders = self
+            .subscribers
+            .senders
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if senders.is_empty() {
+            return;
+        }
+        let event = DownloadEvent {
+            index: self.index,
+            file_name: self.file_name.clone(),
+            event,
+        };
+        // Forget about subscribers that dropped their `Events`:
+        senders.retain(|s| s.unbounded_send(event.clone()).is_ok());
+        drop(senders);
+    }
+}
@@ -0,0 +1,501 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Extraction of downloaded archives
+
+// ----------------------------------------------------------------------
+// - Archive:
+// ----------------------------------------------------------------------
+
+/// An archive format
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Archive {
+    /// An uncompressed `.tar` file.
+    Tar,
+    /// A `.tar.gz` or `.tgz` file.
+    TarGz,
+    /// A `.tar.xz` or `.txz` file.
+    TarXz,
+    /// A `.tar.zst` or `.tzst` file.
+    TarZst,
+    /// A `.zip` file.
+    Zip,
+}
+
+impl Archive {
+    /// Guess the format of an archive from its file name.
+    #[must_use]
+    pub fn from_file_name(file_name: &std::path::Path) -> Option<Self> {
+        let name = file_name
+            .file_name()?
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        [
+            (".tar", Self::Tar),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.xz", Self::TarXz),
+            (".txz", Self::TarXz),
+            (".tar.zst", Self::TarZst),
+            (".tzst", Self::TarZst),
+            (".zip", Self::Zip),
+        ]
+        .iter()
+        .find(|(extension, _)| name.ends_with(extension))
+        .map(|(_, archive)| *archive)
+    }
+
+    /// The format of this archive once it got decompressed.
+    #[must_use]
+    pub const fn decompressed(self) -> Self {
+        match self {
+            Self::Tar | Self::TarGz | Self::TarXz | Self::TarZst => Self::Tar,
+            Self::Zip => Self::Zip,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Extraction:
+// ----------------------------------------------------------------------
+
+/// Where and how to extract a downloaded archive
+///
+/// Extracting needs the `extract` feature.
+#[derive(Clone, Debug)]
+pub struct Extraction {
+    /// The directory to extract into. Relative paths are relative to the
+    /// `download_folder` of the `Downloader`.
+    pub directory: std::path::PathBuf,
+    /// The format of the archive, `None` to guess it from the file name.
+    pub archive: Option<Archive>,
+    /// The number of leading path components to remove from all entries.
+    pub strip_components: usize,
+}
+
+impl Extraction {
+    /// Extract into `directory`.
+    #[must_use]
+    pub fn new(directory: &std::path::Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            archive: None,
+            strip_components: 0,
+        }
+    }
+
+    /// Set the format of the archive
+    ///
+    /// Default is to guess the format from the file name (or URL).
+    #[must_use]
+    pub const fn archive(mut self, archive: Archive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Remove `count` leading path components from all entries
+    ///
+    /// Entries with fewer components are skipped.
+    ///
+    /// Default is 0.
+    #[must_use]
+    pub const fn strip_components(mut self, count: usize) -> Self {
+        self.strip_components = count;
+        self
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+#[cfg(feature = "extract")]
+fn escapes(path: &std::path::Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!(
+            "Archive entry \"{}\" leaves the target directory.",
+            path.to_string_lossy()
+        ),
+    )
+}
+
+/// The path of an archive entry relative to the target directory, with the
+/// first `strip` components removed. `None` if nothing is left.
+#[cfg(feature = "extract")]
+fn relative_path(
+    path: &std::path::Path,
+    strip: usize,
+) -> std::io::Result<Option<std::path::PathBuf>> {
+    let mut result = std::path::PathBuf::new();
+    let mut strip = strip;
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::Normal(_) if strip > 0 => strip -= 1,
+            std::path::Component::Normal(c) => result.push(c),
+            _ => return Err(escapes(path)),
+        }
+    }
+    Ok(Some(result).filter(|r| !r.as_os_str().is_empty()))
+}
+
+/// Create the parent directories of `target`, making sure they stay inside
+/// of `root` even when following symbolic links.
+#[cfg(feature = "extract")]
+fn prepare(root: &std::path::Path, target: &std::path::Path) -> std::io::Result<()> {
+    let inside = |p: &std::path::Path| p.canonicalize().map(|p| p.starts_with(root));
+    let parent = target.parent().unwrap_or(root);
+    let existing = parent.ancestors().find(|a| a.exists()).unwrap_or(root);
+    if !inside(existing)? {
+        return Err(escapes(target));
+    }
+    std::fs::create_dir_all(parent)?;
+    if !inside(parent)? {
+        return Err(escapes(target));
+    }
+    // Never write through a symbolic link that is already there:
+    if target
+        .symlink_metadata()
+        .is_ok_and(|m| m.file_type().is_symlink())
+    {
+        std::fs::remove_file(target)?;
+    }
+    Ok(())
+}
+
+/// Make sure a symbolic link at `target` pointing to `link` stays inside of `root`.
+///
+/// Later entries may replace symbolic links, so `..` is only allowed where it
+/// leaves a real directory: `target` may not be below a symbolic link then
+/// and `link` may not step out of anything but directories that exist.
+#[cfg(feature = "extract")]
+fn check_link(
+    root: &std::path::Path,
+    target: &std::path::Path,
+    link: &std::path::Path,
+) -> std::io::Result<()> {
+    let parent = target.parent().unwrap_or(root);
+    let mut resolved = parent.to_path_buf();
+    for component in link.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::Normal(c) => resolved.push(c),
+            std::path::Component::ParentDir
+                if resolved != root
+                    && resolved
+                        .symlink_metadata()
+                        .is_ok_and(|m| m.file_type().is_dir())
+                    && parent.canonicalize()? == parent =>
+            {
+                resolved.pop();
+            }
+            _ => return Err(escapes(link)),
+        }
+    }
+    if resolved.starts_with(root) {
+        Ok(())
+    } else {
+        Err(escapes(link))
+    }
+}
+
+/// The file at `source` (relative to `root`) to hard link to, resolved on disk.
+///
+/// It has to be inside of `root` and must not be a symbolic link, as a hard
+/// link to that would point somewhere else than the original.
+#[cfg(feature = "extract")]
+fn link_source(
+    root: &std::path::Path,
+    source: &std::path::Path,
+) -> std::io::Result<std::path::PathBuf> {
+    let path = root.join(source);
+    if path.symlink_metadata()?.file_type().is_symlink() {
+        return Err(escapes(source));
+    }
+    let path = path.canonicalize()?;
+    if path.starts_with(root) {
+        Ok(path)
+    } else {
+        Err(escapes(source))
+    }
+}
+
+#[cfg(feature = "extract")]
+fn extract_tar(
+    input: impl std::io::Read,
+    root: &std::path::Path,
+    strip: usize,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let mut result = Vec::new();
+    let mut archive = tar::Archive::new(input);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let Some(relative) = relative_path(&entry.path()?, strip)? else {
+            continue;
+        };
+        let target = root.join(relative);
+        prepare(root, &target)?;
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => {
+                entry.unpack(&target)?;
+                continue;
+            }
+            tar::EntryType::Link => {
+                let source = entry
+                    .link_name()?
+                    .map(|l| relative_path(&l, strip))
+                    .transpose()?
+                    .flatten()
+                    .ok_or_else(|| escapes(&target))?;
+                let source = link_source(root, &source)?;
+                if target.exists() {
+                    std::fs::remove_file(&target)?;
+                }
+                std::fs::hard_link(source, &target)?;
+            }
+            tar::EntryType::Symlink => {
+                let link = entry.link_name()?.ok_or_else(|| escapes(&target))?;
+                check_link(root, &target, &link)?;
+                entry.unpack(&target)?;
+            }
+            _ => {
+                entry.unpack(&target)?;
+            }
+        }
+        result.push(target);
+    }
+    Ok(result)
+}
+
+#[cfg(feature = "extract")]
+fn extract_zip(
+    input: std::fs::File,
+    root: &std::path::Path,
+    strip: usize,
+    progress: &crate::SimpleProgress,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    let mut result = Vec::new();
+    let mut archive = zip::ZipArchive::new(input)?;
+    let mut done = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        done += entry.compressed_size();
+        let path = entry
+            .enclosed_name()
+            .ok_or_else(|| escapes(std::path::Path::new(entry.name())))?;
+        let Some(relative) = relative_path(&path, strip)? else {
+            continue;
+        };
+        let target = root.join(relative);
+        prepare(root, &target)?;
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            let mut output = std::fs::File::create(&target)?;
+            std::io::copy(&mut entry, &mut output)?;
+            #[cfg(unix)]
+            if let Some(mode) = entry.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                output.set_permissions(std::fs::Permissions::from_mode(mode & 0o777))?;
+            }
+            result.push(target);
+        }
+        progress(done);
+    }
+    Ok(result)
+}
+
+/// Extract the `archive` at `path` into `directory`, removing the first
+/// `strip` components of all entries.
+///
+/// `progress` is called with the number of bytes of `path` processed so far.
+/// Returns the paths of all files extracted.
+#[cfg(feature = "extract")]
+pub(crate) fn extract(
+    archive: Archive,
+    path: &std::path::Path,
+    directory: &std::path::Path,
+    strip: usize,
+    progress: &crate::SimpleProgress,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    use crate::decompress::{decoder, Counter, Format};
+
+    std::fs::create_dir_all(directory)?;
+    let root = directory.canonicalize()?;
+    let file = std::fs::File::open(path)?;
+    if archive == Archive::Zip {
+        return extract_zip(file, &root, strip, progress);
+    }
+
+    let input = std::io::BufReader::new(Counter::new(file, progress));
+    match archive {
+        Archive::TarGz => extract_tar(decoder(Format::Gzip, input)?, &root, strip),
+        Archive::TarXz => extract_tar(decoder(Format::Xz, input)?, &root, strip),
+        Archive::TarZst => extract_tar(decoder(Format::Zstd, input)?, &root, strip),
+        Archive::Tar | Archive::Zip => extract_tar(input, &root, strip),
+    }
+}
+
+/// Extracting is not supported without the `extract` feature.
+#[cfg(not(feature = "extract"))]
+pub(crate) fn extract(
+    _archive: Archive,
+    _path: &std::path::Path,
+    _directory: &std::path::Path,
+    _strip: usize,
+    _progress: &crate::SimpleProgress,
+) -> std::io::Result<Vec<std::path::PathBuf>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Can not extract archives: The \"extract\" feature is disabled.",
+    ))
+}
+
+#[cfg(all(test, feature = "extract"))]
+mod tests {
+    use super::*;
+
+    enum Entry<'a> {
+        Dir(&'a str),
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    fn tar(entries: &[Entry<'_>]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(0o755);
+            header.set_size(0);
+            match entry {
+                Entry::Dir(path) => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    builder.append_data(&mut header, path, std::io::empty())
+                }
+                Entry::File(path, content) => {
+                    header.set_size(content.len() as u64);
+                    builder.append_data(&mut header, path, content.as_bytes())
+                }
+                Entry::Symlink(path, link) => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    builder.append_link(&mut header, path, link)
+                }
+                Entry::HardLink(path, link) => {
+                    header.set_entry_type(tar::EntryType::Link);
+                    builder.append_link(&mut header, path, link)
+                }
+            }
+            .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// Extract `entries` into a fresh directory, which has a `secret.txt` next to it.
+    fn run(name: &str, entries: &[Entry<'_>]) -> (std::path::PathBuf, std::io::Result<()>) {
+        let base = std::env::temp_dir().join(format!(
+            "{}-extract-{name}-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&base);
+        let root = base.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        let root = root.canonicalize().unwrap();
+        let result = extract_tar(tar(entries).as_slice(), &root, 0).map(|_| ());
+        (root, result)
+    }
+
+    #[test]
+    fn hard_link_through_stacked_symlinks_fails() {
+        let (root, result) = run(
+            "stacked",
+            &[
+                Entry::Dir("a/"),
+                Entry::Dir("a/b/"),
+                Entry::Symlink("a/up", ".."),
+                Entry::Symlink("a/b/x", "../up/.."),
+                Entry::HardLink("stolen.txt", "a/b/x/secret.txt"),
+            ],
+        );
+        assert!(result.is_err());
+        assert!(!root.join("stolen.txt").exists());
+        assert!(!root.join("a/b/x").exists());
+    }
+
+    #[test]
+    fn symlink_created_before_the_one_it_goes_through_fails() {
+        let (root, result) = run(
+            "reordered",
+            &[
+                Entry::Dir("a/"),
+                Entry::Dir("a/b/"),
+                Entry::Symlink("a/b/x", "../up/.."),
+                Entry::Symlink("a/up", ".."),
+                Entry::HardLink("stolen.txt", "a/b/x/secret.txt"),
+            ],
+        );
+        assert!(result.is_err());
+        assert!(!root.join("stolen.txt").exists());
+    }
+
+    #[test]
+    fn symlink_below_replaced_symlink_fails() {
+        let (_, result) = run(
+            "replaced",
+            &[
+                Entry::Dir("p1/d/"),
+                Entry::Dir("p2/"),
+                Entry::Symlink("s", "p1"),
+                Entry::Symlink("s/d/x", "../y"),
+            ],
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn symlink_leaving_root_fails() {
+        let (_, result) = run("parent", &[Entry::Symlink("up", "../secret.txt")]);
+        assert!(result.is_err());
+        let (_, result) = run("absolute", &[Entry::Symlink("abs", "/etc/passwd")]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn hard_link_to_symlink_fails() {
+        let (root, result) = run(
+            "linked-link",
+            &[
+                Entry::Dir("a/b/"),
+                Entry::File("x", "x"),
+                Entry::Symlink("a/b/l", "../../x"),
+                Entry::HardLink("l2", "a/b/l"),
+            ],
+        );
+        assert!(result.is_err());
+        assert!(root.join("l2").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn links_inside_root_work() {
+        let (root, result) = run(
+            "inside",
+            &[
+                Entry::Dir("lib64/"),
+                Entry::File("lib64/libfoo.so.1", "foo"),
+                Entry::Dir("lib/"),
+                Entry::Symlink("lib/libfoo.so", "../lib64/libfoo.so.1"),
+                Entry::Symlink("libfoo.so", "lib64/libfoo.so.1"),
+                Entry::HardLink("copy.so", "lib64/libfoo.so.1"),
+            ],
+        );
+        result.unwrap();
+        for path in ["lib/libfoo.so", "libfoo.so", "copy.so"] {
+            assert_eq!(std::fs::read_to_string(root.join(path)).unwrap(), "foo");
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Hooks run after a download landed
+
+use crate::DownloadSummary;
+
+// ----------------------------------------------------------------------
+// - Types:
+// ----------------------------------------------------------------------
+
+/// The error a `Hook` fails with
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A hook run on a thread that may block
+///
+/// It gets the summary of the finished download and can change it, e.g. to
+/// update `file_name` after moving the file.
+pub type Blocking =
+    std::sync::Arc<dyn Fn(&mut DownloadSummary) -> Result<(), HookError> + Send + Sync>;
+
+/// A hook run on the runtime driving the downloads
+///
+/// It gets the summary of the finished download and has to hand it back,
+/// changed or not, together with its result.
+pub type Async = std::sync::Arc<
+    dyn Fn(
+            DownloadSummary,
+        ) -> futures::future::BoxFuture<'static, (DownloadSummary, Result<(), HookError>)>
+        + Send
+        + Sync,
+>;
+
+/// Something to do with a file once it was downloaded, verified and handed to
+/// the sink
+///
+/// Hooks of a `Download` run one after the other. The first one failing fails
+/// the download with `Error::Hook`, leaving the file where it is. Downloads
+/// that did not fetch a file (e.g. as it existed already) do not run any hooks.
+#[derive(Clone)]
+pub enum Hook {
+    /// Run on a thread that may block.
+    Blocking(Blocking),
+    /// Run on the runtime driving the downloads.
+    Async(Async),
+}
+
+impl Hook {
+    /// Create a hook running `func` on a thread that may block.
+    #[must_use]
+    pub fn blocking<F>(func: F) -> Self
+    where
+        F: Fn(&mut DownloadSummary) -> Result<(), HookError> + Send + Sync + 'static,
+    {
+        Self::Blocking(std::sync::Arc::new(func))
+    }
+
+    /// Create a hook awaiting the future returned by `func`.
+    #[must_use]
+    pub fn future<F, Fut>(func: F) -> Self
+    where
+        F: Fn(DownloadSummary) -> Fut + Send + Sync + 'static,
+        Fut:
+            std::future::Future<Output = (DownloadSummary, Result<(), HookError>)> + Send + 'static,
+    {
+        Self::Async(std::sync::Arc::new(move |summary| Box::pin(func(summary))))
+    }
+
+    /// Run this hook on `summary`.
+    ///
+    /// A blocking hook that panics fails with a `HookError`.
+    pub(crate) async fn run(
+        &self,
+        summary: DownloadSummary,
+    ) -> (DownloadSummary, Result<(), HookError>) {
+        match self {
+            Self::Blocking(func) => {
+                let func = func.clone();
+                // Shared with the hook, so that the summary survives it panicking:
+                let shared = std::sync::Arc::new(std::sync::Mutex::new(Some(summary)));
+                let hooked = shared.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut summary = hooked
+                        .lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner);
+                    summary.as_mut().map_or(Ok(()), |s| func(s))
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.into()));
+                let summary = shared
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .take()
+                    .expect("Only the hook runner takes the summary");
+                (summary, result)
+            }
+            Self::Async(func) => func(summary).await,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Helpers:
+// ----------------------------------------------------------------------
+
+/// Make the downloaded file executable for everybody who can read it
+///
+/// This does nothing on platforms without executable permission bits.
+#[must_use]
+pub fn make_executable() -> Hook {
+    Hook::blocking(|summary: &mut DownloadSummary| {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mut permissions = std::fs::metadata(&summary.file_name)?.permissions();
+            let mode = permissions.mode();
+            permissions.set_mode(mode | ((mode & 0o444) >> 2));
+            std::fs::set_permissions(&summary.file_name, permissions)?;
+        }
+        #[cfg(not(unix))]
+        let _ = summary;
+        Ok(())
+    })
+}
+
+/// Move the downloaded file into `directory`, keeping its name
+///
+/// The `directory` is created if necessary and `DownloadSummary::file_name`
+/// is updated to the new location.
+#[must_use]
+pub fn move_into(directory: &std::path::Path) -> Hook {
+    let directory = directory.to_path_buf();
+    Hook::blocking(move |summary: &mut DownloadSummary| {
+        let name = summary
+            .file_name
+            .file_name()
+            .ok_or("The download has no file name")?;
+        let target = directory.join(name);
+        std::fs::create_dir_all(&directory)?;
+        if std::fs::rename(&summary.file_name, &target).is_err() {
+            std::fs::copy(&summary.file_name, &target)?;
+            std::fs::remove_file(&summary.file_name)?;
+        }
+        summary.file_name = target;
+        Ok(())
+    })
+}
@@ -22,18 +22,31 @@
 )]
 // Clippy:
 #![warn(clippy::all, clippy::nursery, clippy::pedantic)]
-#![allow(clippy::non_ascii_literal)]
+#![allow(
+    clippy::non_ascii_literal,
+    clippy::duration_suboptimal_units,
+    clippy::result_large_err
+)]
 
 pub mod backend;
+pub mod bandwidth;
+pub mod cancel;
+pub mod decompress;
 pub mod download;
 pub mod downloader;
+pub mod event;
+pub mod extract;
+pub mod hook;
+pub mod metadata;
+pub mod mirror;
 pub mod progress;
+pub mod retry;
 pub mod verify;
 
 pub use crate::download::Download;
 pub use crate::downloader::Downloader;
 pub use crate::progress::Progress;
-pub use crate::verify::{SimpleProgress, Verification, Verify};
+pub use crate::verify::{Failure, SimpleProgress, StreamingVerify, Verification, Verify};
 
 // ----------------------------------------------------------------------
 // - Error:
@@ -48,12 +61,40 @@ pub enum Error {
     /// A Definition of a `Download` is incomplete
     #[error("Download definition: {0}")]
     DownloadDefinition(String),
-    /// Writing into a file failed during download.
-    #[error("File creation failed: {0}")]
-    File(DownloadSummary),
+    /// The file to download exists already.
+    #[error("File exists already: {0}")]
+    FileExists(DownloadSummary),
+    /// Creating, writing or moving a file (or writing into a sink) failed during download.
+    #[error("File operation failed ({1}) for {0}")]
+    File(DownloadSummary, #[source] std::io::Error),
     /// A download failed
     #[error("Download failed for {0}")]
     Download(DownloadSummary),
+    /// Connecting to the server failed, this includes TLS handshake problems.
+    #[error("Connection failed ({1}) for {0}")]
+    Connection(DownloadSummary, #[source] reqwest::Error),
+    /// The server did not respond in time.
+    #[error("Timed out ({1}) for {0}")]
+    Timeout(DownloadSummary, #[source] reqwest::Error),
+    /// Sending the request or receiving the response failed.
+    #[error("Transfer failed ({1}) for {0}")]
+    Transfer(DownloadSummary, #[source] reqwest::Error),
+    /// The server closed the connection before all data announced in
+    /// `Content-Length` was received.
+    #[error("Download truncated after {1} of {2} bytes for {0}")]
+    Truncated(DownloadSummary, u64, u64),
+    /// The download was cancelled through a `cancel::Token`.
+    #[error("Download cancelled for {0}")]
+    Cancelled(DownloadSummary),
+    /// Decompressing the downloaded data failed.
+    #[error("Decompression failed ({1}) for {0}")]
+    Decompression(DownloadSummary, #[source] std::io::Error),
+    /// Extracting the downloaded archive failed.
+    #[error("Extraction failed ({1}) for {0}")]
+    Extraction(DownloadSummary, #[source] std::io::Error),
+    /// A `hook::Hook` run after the download failed.
+    #[error("Hook failed ({1}) for {0}")]
+    Hook(DownloadSummary, #[source] crate::hook::HookError),
     /// Download file verification failed.
     #[error("Verification failed for {0}")]
     Verification(DownloadSummary),
@@ -68,12 +109,28 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 /// The result of a `Download`
 pub struct DownloadSummary {
-    /// A list of attempted downloads with URL and status code.
-    pub status: Vec<(String, u16)>,
+    /// A list of attempted downloads with URL and status code. The status code is
+    /// `None` if no response was received.
+    pub status: Vec<(String, Option<u16>)>,
     /// The path this URL has been downloaded to.
     pub file_name: std::path::PathBuf,
     /// File verification status
     pub verified: Verification,
+    /// Number of bytes that were kept from an earlier, interrupted run
+    /// instead of being downloaded again.
+    pub bytes_resumed: u64,
+    /// Number of bytes received from the network.
+    pub bytes_fetched: u64,
+    /// What was done with the file.
+    pub action: crate::download::FileAction,
+    /// The downloaded data for downloads into `download::Sink::Memory`.
+    pub data: Option<Vec<u8>>,
+    /// The response the file was received with, `None` if the file was not
+    /// downloaded.
+    pub response: Option<Box<crate::metadata::Response>>,
+    /// The files extracted from the downloaded archive, for downloads with an
+    /// `extract::Extraction`.
+    pub extracted: Vec<std::path::PathBuf>,
 }
 
 fn to_fmt(f: &mut std::fmt::Formatter<'_>, summary: &DownloadSummary) -> std::fmt::Result {
@@ -83,19 +140,35 @@ fn to_fmt(f: &mut std::fmt::Formatter<'_>, summary: &DownloadSummary) -> std::fm
         summary.file_name.to_string_lossy(),
         match summary.verified {
             Verification::NotVerified => "unverified",
-            Verification::Failed => "FAILED",
+            Verification::Failed(_) => "FAILED",
             Verification::Ok => "Ok",
         },
     )?;
-    for i in 0..summary.status.len() {
+    if let Verification::Failed(failure) = &summary.verified {
+        writeln!(f, "  {failure}")?;
+    }
+    if summary.action != crate::download::FileAction::Downloaded {
+        writeln!(f, "  {}", summary.action)?;
+    }
+    if summary.bytes_resumed > 0 {
         writeln!(
             f,
-            "  {}: {} with status {}",
-            i + 1,
-            summary.status[i].0,
-            summary.status[i].1
+            "  resumed {} bytes, fetched {} bytes",
+            summary.bytes_resumed, summary.bytes_fetched
         )?;
     }
+    for i in 0..summary.status.len() {
+        match summary.status[i].1 {
+            Some(status) => writeln!(
+                f,
+                "  {}: {} with status {}",
+                i + 1,
+                summary.status[i].0,
+                status
+            )?,
+            None => writeln!(f, "  {}: {} without response", i + 1, summary.status[i].0)?,
+        }
+    }
     Ok(())
 }
 
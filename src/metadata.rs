@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Metadata about downloaded files, kept between runs
+
+// ----------------------------------------------------------------------
+// - Metadata:
+// ----------------------------------------------------------------------
+
+/// What the server said about a downloaded file
+///
+/// Downloads using `ExistingFile::Update` store this in a sidecar file next
+/// to the downloaded file, so that later runs can ask the server whether the
+/// file changed since.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metadata {
+    /// The `ETag` header of the response.
+    pub etag: Option<String>,
+    /// The `Last-Modified` header of the response.
+    pub last_modified: Option<String>,
+}
+
+impl Metadata {
+    /// Collect the metadata from the `headers` of a response.
+    pub(crate) fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(std::borrow::ToOwned::to_owned)
+        };
+        Self {
+            etag: value(reqwest::header::ETAG),
+            last_modified: value(reqwest::header::LAST_MODIFIED),
+        }
+    }
+
+    /// Is there nothing to ask the server about?
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// The value to send in an `If-Range` header. Weak `ETag`s are not allowed
+    /// there, so fall back to `Last-Modified` for those.
+    pub(crate) fn validator(&self) -> Option<String> {
+        self.etag
+            .clone()
+            .filter(|e| !e.starts_with("W/"))
+            .or_else(|| self.last_modified.clone())
+    }
+
+    /// The path of the sidecar file storing the metadata of `file_name`.
+    #[must_use]
+    pub fn sidecar(file_name: &std::path::Path) -> std::path::PathBuf {
+        let mut name = file_name.as_os_str().to_owned();
+        name.push(".meta");
+        std::path::PathBuf::from(name)
+    }
+
+    /// Load the metadata stored for `file_name`, `None` if there is none.
+    #[must_use]
+    pub fn load(file_name: &std::path::Path) -> Option<Self> {
+        let content = std::fs::read_to_string(Self::sidecar(file_name)).ok()?;
+        let mut result = Self::default();
+        for (name, value) in content.lines().filter_map(|l| l.split_once(':')) {
+            let value = Some(value.trim().to_owned()).filter(|v| !v.is_empty());
+            match name.trim().to_ascii_lowercase().as_str() {
+                "etag" => result.etag = value,
+                "last-modified" => result.last_modified = value,
+                _ => {}
+            }
+        }
+        Some(result).filter(|m| !m.is_empty())
+    }
+
+    /// Store the metadata for `file_name`, removing outdated metadata if there
+    /// is nothing to store.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the sidecar file can not be written.
+    pub fn store(&self, file_name: &std::path::Path) -> std::io::Result<()> {
+        let sidecar = Self::sidecar(file_name);
+        if self.is_empty() {
+            return match std::fs::remove_file(sidecar) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let content: String = [("ETag", &self.etag), ("Last-Modified", &self.last_modified)]
+            .iter()
+            .filter_map(|(name, value)| value.as_ref().map(|v| format!("{name}: {v}\n")))
+            .collect();
+        std::fs::write(sidecar, content)
+    }
+
+    /// The headers asking the server to only send the file if it changed.
+    pub(crate) fn conditional_headers(&self) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+        let mut insert = |name, value: &Option<String>| {
+            if let Some(value) = value
+                .as_deref()
+                .and_then(|v| reqwest::header::HeaderValue::from_str(v).ok())
+            {
+                headers.insert(name, value);
+            }
+        };
+        insert(reqwest::header::IF_NONE_MATCH, &self.etag);
+        insert(reqwest::header::IF_MODIFIED_SINCE, &self.last_modified);
+        headers
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Response:
+// ----------------------------------------------------------------------
+
+/// The response a downloaded file was received with
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// The URL after following all redirects.
+    pub url: String,
+    /// The HTTP status code.
+    pub status: u16,
+    /// The headers of the response.
+    pub headers: reqwest::header::HeaderMap,
+    /// The `Content-Type` header of the response.
+    pub content_type: Option<String>,
+    /// The length of the complete file, if known. This differs from the
+    /// `Content-Length` header for partial responses.
+    pub content_length: Option<u64>,
+}
+
+impl Response {
+    /// The value of the header `name`, if it is set and readable.
+    #[must_use]
+    pub fn header(&self, name: reqwest::header::HeaderName) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    /// The date in the `Last-Modified` header, if any.
+    #[must_use]
+    pub fn last_modified(&self) -> Option<std::time::SystemTime> {
+        httpdate::parse_http_date(self.header(reqwest::header::LAST_MODIFIED)?).ok()
+    }
+
+    /// The `Metadata` to store for the downloaded file.
+    #[must_use]
+    pub fn metadata(&self) -> Metadata {
+        Metadata::from_headers(&self.headers)
+    }
+}
@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Mirror selection and health tracking
+
+use rand::Rng;
+use std::convert::TryFrom;
+
+// ----------------------------------------------------------------------
+// - Order:
+// ----------------------------------------------------------------------
+
+/// How to pick the next mirror of a `Download`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Order {
+    /// Pick a mirror at random.
+    Random,
+    /// Use mirrors in the order they were given, the first one has the highest priority.
+    Ordered,
+    /// Pick a mirror at random, preferring mirrors with a higher weight.
+    Weighted,
+}
+
+// ----------------------------------------------------------------------
+// - HostStats:
+// ----------------------------------------------------------------------
+
+/// After this many failures in a row a host is considered unhealthy.
+const UNHEALTHY_AFTER: u32 = 3;
+
+/// Health information about one host
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HostStats {
+    /// Number of successful requests.
+    pub successes: u32,
+    /// Number of failed requests.
+    pub failures: u32,
+    /// Number of requests that failed since the last successful one.
+    pub consecutive_failures: u32,
+    /// Average time until the response headers arrived.
+    pub latency: Option<std::time::Duration>,
+    /// Average transfer speed in bytes per second.
+    pub throughput: Option<u64>,
+}
+
+impl HostStats {
+    /// Is this host considered to be working?
+    #[must_use]
+    pub const fn is_healthy(&self) -> bool {
+        self.consecutive_failures < UNHEALTHY_AFTER
+    }
+}
+
+/// Weigh new samples with 30% when averaging.
+fn average(old: Option<u64>, sample: u64) -> u64 {
+    old.map_or(sample, |old| (old * 7 + sample * 3) / 10)
+}
+
+// ----------------------------------------------------------------------
+// - Health:
+// ----------------------------------------------------------------------
+
+/// Health information on all hosts, shared by all downloads of a `Downloader`.
+#[derive(Default)]
+pub(crate) struct Health {
+    hosts: std::sync::Mutex<std::collections::HashMap<String, HostStats>>,
+}
+
+/// The `host:port` of `url`, used to identify hosts.
+pub(crate) fn host(url: &str) -> String {
+    reqwest::Url::parse(url).map_or_else(
+        |_| url.to_owned(),
+        |u| {
+            format!(
+                "{}:{}",
+                u.host_str().unwrap_or_default(),
+                u.port_or_known_default().unwrap_or_default()
+            )
+        },
+    )
+}
+
+impl Health {
+    pub(crate) fn stats(&self) -> std::collections::HashMap<String, HostStats> {
+        self.hosts.lock().unwrap().clone()
+    }
+
+    fn is_healthy(&self, url: &str) -> bool {
+        self.hosts
+            .lock()
+            .unwrap()
+            .get(&host(url))
+            .is_none_or(HostStats::is_healthy)
+    }
+
+    /// Record a successful request to `url`.
+    pub(crate) fn success(
+        &self,
+        url: &str,
+        latency: Option<std::time::Duration>,
+        bytes: u64,
+        elapsed: std::time::Duration,
+    ) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = hosts.entry(host(url)).or_default();
+        stats.successes += 1;
+        stats.consecutive_failures = 0;
+        if let Some(latency) = latency {
+            let old = stats
+                .latency
+                .map(|l| u64::try_from(l.as_micros()).unwrap_or(u64::MAX));
+            let sample = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+            stats.latency = Some(std::time::Duration::from_micros(average(old, sample)));
+        }
+        let millis = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        if bytes > 0 && millis > 0 {
+            stats.throughput = Some(average(stats.throughput, bytes * 1000 / millis));
+        }
+        drop(hosts);
+    }
+
+    /// Record a failed request to `url`.
+    pub(crate) fn failure(&self, url: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = hosts.entry(host(url)).or_default();
+        stats.failures += 1;
+        stats.consecutive_failures += 1;
+        drop(hosts);
+    }
+}
+
+// ----------------------------------------------------------------------
+// - Selector:
+// ----------------------------------------------------------------------
+
+/// Picks mirrors for one download, trying every mirror once before any mirror
+/// is used again.
+pub(crate) struct Selector<'a> {
+    urls: &'a [String],
+    order: Order,
+    weights: &'a [u32],
+    tried: Vec<bool>,
+    failed: Vec<bool>,
+    /// Mirrors that gave an answer not worth retrying and are never used again.
+    rejected: Vec<bool>,
+    first: Option<usize>,
+}
+
+impl<'a> Selector<'a> {
+    pub(crate) fn new(urls: &'a [String], order: Order, weights: &'a [u32]) -> Self {
+        assert!(!urls.is_empty());
+        Self {
+            urls,
+            order,
+            weights,
+            tried: vec![false; urls.len()],
+            failed: vec![false; urls.len()],
+            rejected: vec![false; urls.len()],
+            first: None,
+        }
+    }
+
+    /// Start with the mirror at `index` (modulo the number of mirrors).
+    pub(crate) const fn starting_at(mut self, index: usize) -> Self {
+        self.first = Some(index % self.urls.len());
+        self
+    }
+
+    /// Remember that the last attempt using `url` failed.
+    pub(crate) fn failed(&mut self, url: &str) {
+        if let Some(i) = self.urls.iter().position(|u| u == url) {
+            self.failed[i] = true;
+        }
+    }
+
+    /// Never use `url` again.
+    ///
+    /// Returns `false` if there are no mirrors left to use.
+    pub(crate) fn reject(&mut self, url: &str) -> bool {
+        if let Some(i) = self.urls.iter().position(|u| u == url) {
+            self.rejected[i] = true;
+        }
+        self.rejected.iter().any(|r| !*r)
+    }
+
+    /// Pick the next URL to try.
+    pub(crate) fn next(&mut self, health: &Health) -> String {
+        let index = self.first.take().unwrap_or_else(|| self.pick(health));
+        self.tried[index] = true;
+        self.urls[index].clone()
+    }
+
+    fn pick(&mut self, health: &Health) -> usize {
+        if self.tried.iter().all(|t| *t) {
+            // Start another round, leaving out mirrors that failed if possible:
+            let all_failed = (0..self.urls.len())
+                .filter(|i| !self.rejected[*i])
+                .all(|i| self.failed[i]);
+            for i in 0..self.tried.len() {
+                self.tried[i] = self.rejected[i] || (self.failed[i] && !all_failed);
+            }
+            self.failed = vec![false; self.urls.len()];
+        }
+
+        let untried: Vec<usize> = (0..self.urls.len()).filter(|i| !self.tried[*i]).collect();
+        let healthy: Vec<usize> = untried
+            .iter()
+            .copied()
+            .filter(|i| health.is_healthy(&self.urls[*i]))
+            .collect();
+        let candidates = if healthy.is_empty() { untried } else { healthy };
+
+        match self.order {
+            Order::Ordered => candidates[0],
+            Order::Random => candidates[rand::thread_rng().gen_range(0..candidates.len())],
+            Order::Weighted => {
+                let weight = |i: usize| u64::from(self.weights.get(i).copied().unwrap_or(1));
+                let total: u64 = candidates.iter().map(|i| weight(*i)).sum();
+                if total == 0 {
+                    return candidates[0];
+                }
+                let mut target = rand::thread_rng().gen_range(0..total);
+                for i in &candidates {
+                    if target < weight(*i) {
+                        return *i;
+                    }
+                    target -= weight(*i);
+                }
+                candidates[candidates.len() - 1]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls() -> Vec<String> {
+        [
+            "http://a.example/f",
+            "http://b.example/f",
+            "http://c.example/f",
+        ]
+        .iter()
+        .map(|u| (*u).to_owned())
+        .collect()
+    }
+
+    fn make_unhealthy(health: &Health, url: &str) {
+        for _ in 0..UNHEALTHY_AFTER {
+            health.failure(url);
+        }
+    }
+
+    #[test]
+    fn every_mirror_is_tried_once_first() {
+        let urls = urls();
+        let health = Health::default();
+        let mut selector = Selector::new(&urls, Order::Random, &[]);
+        let mut picked: Vec<String> = (0..3).map(|_| selector.next(&health)).collect();
+        picked.sort();
+        assert_eq!(picked, urls);
+    }
+
+    #[test]
+    fn unhealthy_hosts_are_skipped() {
+        let urls = urls();
+        let health = Health::default();
+        make_unhealthy(&health, &urls[0]);
+        let mut selector = Selector::new(&urls, Order::Ordered, &[]);
+        assert_eq!(selector.next(&health), urls[1]);
+        assert_eq!(selector.next(&health), urls[2]);
+        // Unhealthy hosts are still used once nothing else is left:
+        assert_eq!(selector.next(&health), urls[0]);
+    }
+
+    #[test]
+    fn rejected_mirrors_are_not_used_again() {
+        let urls = urls();
+        let health = Health::default();
+        let mut selector = Selector::new(&urls, Order::Ordered, &[]);
+        assert_eq!(selector.next(&health), urls[0]);
+        selector.failed(&urls[0]);
+        assert!(selector.reject(&urls[0]));
+        for _ in 0..2 {
+            for url in &urls[1..] {
+                assert_eq!(&selector.next(&health), url);
+                selector.failed(url);
+            }
+        }
+        assert!(selector.reject(&urls[1]));
+        assert!(!selector.reject(&urls[2]));
+    }
+
+    #[test]
+    fn failed_mirrors_are_left_out_of_the_next_round() {
+        let urls = urls();
+        let health = Health::default();
+        let mut selector = Selector::new(&urls, Order::Ordered, &[]);
+        for url in &urls {
+            assert_eq!(&selector.next(&health), url);
+        }
+        selector.failed(&urls[0]);
+        assert_eq!(selector.next(&health), urls[1]);
+    }
+
+    #[test]
+    fn all_unhealthy_hosts_recover() {
+        let urls = urls();
+        let health = Health::default();
+        for url in &urls {
+            make_unhealthy(&health, url);
+        }
+        let mut selector = Selector::new(&urls, Order::Ordered, &[]);
+        assert_eq!(selector.next(&health), urls[0]);
+        health.success(&urls[2], None, 0, std::time::Duration::ZERO);
+        assert!(health.is_healthy(&urls[2]));
+        assert_eq!(selector.next(&health), urls[2]);
+        assert_eq!(health.stats()[&host(&urls[2])].consecutive_failures, 0);
+    }
+}
@@ -0,0 +1,241 @@
+// SPDX-License-Identifier: LGPL-3.0-or-later
+// Copyright (C) 2020 Tobias Hunger <tobias.hunger@gmail.com>
+
+//! Retry policies
+
+// ----------------------------------------------------------------------
+// - Policy:
+// ----------------------------------------------------------------------
+
+/// Decides which failures are worth another try and how long to wait before that.
+///
+/// Delays grow exponentially from `initial_delay` by `multiplier` with each retry
+/// and are capped at `max_delay`.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    /// The delay before the first retry.
+    pub initial_delay: std::time::Duration,
+    /// The longest delay to ever wait between two attempts.
+    pub max_delay: std::time::Duration,
+    /// The factor the delay grows by with each retry.
+    pub multiplier: u32,
+    /// If set to `true`, delays are randomized to somewhere between half of and
+    /// the full computed delay, so that parallel downloads do not retry in lockstep.
+    pub jitter: bool,
+    /// If set to `true`, the `Retry-After` header of `429` and `503` responses
+    /// is used as the delay. Servers asking to wait longer than `max_delay` are
+    /// not retried.
+    pub respect_retry_after: bool,
+    /// HTTP status codes that are worth retrying.
+    pub retry_statuses: Vec<u16>,
+    /// If set to `true`, connection problems, timeouts and interrupted transfers
+    /// are retried.
+    pub retry_transport_errors: bool,
+}
+
+impl Policy {
+    /// A policy that retries right away, without any delay.
+    ///
+    /// `Retry-After` headers are ignored: Servers asking to wait get retried
+    /// right away as well instead of not at all.
+    #[must_use]
+    pub fn immediate() -> Self {
+        Self {
+            initial_delay: std::time::Duration::ZERO,
+            max_delay: std::time::Duration::ZERO,
+            jitter: false,
+            respect_retry_after: false,
+            ..Self::default()
+        }
+    }
+
+    /// Set the initial and maximum delay between attempts.
+    #[must_use]
+    pub const fn backoff(
+        mut self,
+        initial_delay: std::time::Duration,
+        max_delay: std::time::Duration,
+    ) -> Self {
+        self.initial_delay = initial_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the factor the delay grows by with each retry.
+    #[must_use]
+    pub const fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Enable or disable randomizing delays.
+    #[must_use]
+    pub const fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Enable or disable honoring `Retry-After` headers.
+    #[must_use]
+    pub const fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+
+    /// Set the HTTP status codes that are worth retrying.
+    #[must_use]
+    pub fn retry_statuses(mut self, statuses: &[u16]) -> Self {
+        self.retry_statuses = statuses.to_vec();
+        self
+    }
+
+    /// Enable or disable retrying on connection problems and interrupted transfers.
+    #[must_use]
+    pub const fn retry_transport_errors(mut self, retry: bool) -> Self {
+        self.retry_transport_errors = retry;
+        self
+    }
+
+    /// Is an attempt that ended with `status` worth retrying? A `status` of
+    /// `None` stands for a transport error.
+    #[must_use]
+    pub fn is_retryable(&self, status: Option<u16>) -> bool {
+        status.map_or(self.retry_transport_errors, |s| {
+            self.retry_statuses.contains(&s)
+        })
+    }
+
+    /// Does `retry_after` ask to wait longer than this policy allows?
+    #[must_use]
+    pub fn exceeds_max_delay(&self, retry_after: Option<std::time::Duration>) -> bool {
+        self.respect_retry_after && retry_after.is_some_and(|d| d > self.max_delay)
+    }
+
+    /// The delay to wait before retry number `retry` (starting at 1), taking
+    /// a `Retry-After` value sent by the server into account.
+    #[must_use]
+    pub fn delay(
+        &self,
+        retry: u16,
+        retry_after: Option<std::time::Duration>,
+    ) -> std::time::Duration {
+        if let Some(delay) = retry_after.filter(|_| self.respect_retry_after) {
+            return delay;
+        }
+
+        let factor = self
+            .multiplier
+            .checked_pow(u32::from(retry.saturating_sub(1)))
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        if self.jitter && delay > std::time::Duration::ZERO {
+            use rand::Rng;
+
+            let half = delay / 2;
+            half + rand::thread_rng().gen_range(std::time::Duration::ZERO..=half)
+        } else {
+            delay
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            initial_delay: std::time::Duration::from_millis(500),
+            max_delay: std::time::Duration::from_secs(30),
+            multiplier: 2,
+            jitter: true,
+            respect_retry_after: true,
+            retry_statuses: vec![408, 425, 429, 500, 502, 503, 504],
+            retry_transport_errors: true,
+        }
+    }
+}
+
+/// Parse a `Retry-After` header value, which is either a number of seconds
+/// or a HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    value.parse::<u64>().map_or_else(
+        |_| {
+            httpdate::parse_http_date(value).ok().map(|d| {
+                d.duration_since(std::time::SystemTime::now())
+                    .unwrap_or_default()
+            })
+        },
+        |s| Some(std::time::Duration::from_secs(s)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn delay_grows_and_is_capped() {
+        let policy = Policy::default()
+            .backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .jitter(false);
+        assert_eq!(policy.delay(1, None), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(200));
+        assert_eq!(policy.delay(4, None), Duration::from_millis(800));
+        assert_eq!(policy.delay(5, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(u16::MAX, None), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn delay_jitter_stays_within_bounds() {
+        let policy = Policy::default().backoff(Duration::from_secs(2), Duration::from_secs(2));
+        for _ in 0..100 {
+            let delay = policy.delay(1, None);
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn delay_respects_retry_after() {
+        let retry_after = Some(Duration::from_secs(5));
+        let policy = Policy::default().jitter(false);
+        assert_eq!(policy.delay(1, retry_after), Duration::from_secs(5));
+        assert!(!policy.exceeds_max_delay(retry_after));
+        assert!(policy.exceeds_max_delay(Some(Duration::from_secs(60))));
+
+        let policy = policy.respect_retry_after(false);
+        assert_eq!(policy.delay(1, retry_after), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn immediate_retries_despite_retry_after() {
+        let policy = Policy::immediate();
+        let retry_after = Some(Duration::from_secs(5));
+        assert!(policy.is_retryable(Some(503)));
+        assert!(!policy.exceeds_max_delay(retry_after));
+        assert_eq!(policy.delay(3, retry_after), Duration::ZERO);
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let later = std::time::SystemTime::now() + Duration::from_secs(3600);
+        let delay = parse_retry_after(&httpdate::fmt_http_date(later)).unwrap();
+        assert!(delay > Duration::from_secs(3590) && delay <= Duration::from_secs(3600));
+
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+    }
+}
@@ -14,31 +14,160 @@
 pub type SimpleProgress = dyn Fn(u64) + Sync;
 
 /// A callback to used to verify the download.
+///
+/// It gets passed the path of the temporary file the download was written to,
+/// before that is moved to its final location. A failed verification reports
+/// why in its `Failure`.
 pub type Verify =
     std::sync::Arc<dyn Fn(std::path::PathBuf, &SimpleProgress) -> Verification + Send + Sync>;
 
+/// Verifies a download while it is received
+///
+/// It is fed the data of the file in order, starting at its first byte.
+pub trait Streaming: Send {
+    /// Feed the next `data` of the file.
+    fn update(&mut self, data: &[u8]);
+
+    /// All data of the file was fed: Is it the expected data?
+    fn finish(self: Box<Self>) -> Verification;
+}
+
+/// Creates a new `Streaming` verifier for a download
+///
+/// A new verifier is created whenever a download needs to start over.
+pub type StreamingVerify = std::sync::Arc<dyn Fn() -> Box<dyn Streaming> + Send + Sync>;
+
 /// The possible states of file verification
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Verification {
     /// The file has not been verified at all.
     NotVerified,
     /// The file failed the verification process.
-    Failed,
+    Failed(Failure),
     /// The file passed the verification process.
     Ok,
 }
 
+impl Verification {
+    /// The file failed verification for `reason`.
+    #[must_use]
+    pub fn failed(reason: &str) -> Self {
+        Self::Failed(Failure::new(reason))
+    }
+
+    /// Did the file pass verification?
+    #[must_use]
+    pub const fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+
+    /// Did the file fail verification?
+    #[must_use]
+    pub const fn is_failed(&self) -> bool {
+        matches!(self, Self::Failed(_))
+    }
+
+    /// Combine the results of two verifications: Failing one fails both and
+    /// passing one is enough if the other did not verify anything.
+    pub(crate) fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (failed @ Self::Failed(_), _) | (_, failed @ Self::Failed(_)) => failed,
+            (Self::Ok, _) | (_, Self::Ok) => Self::Ok,
+            (Self::NotVerified, Self::NotVerified) => Self::NotVerified,
+        }
+    }
+}
+
 impl std::fmt::Display for Verification {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match &self {
-                Self::NotVerified => "not verified",
-                Self::Failed => "FAILED",
-                Self::Ok => "Ok",
-            }
-        )
+        match &self {
+            Self::NotVerified => write!(f, "not verified"),
+            Self::Failed(failure) => write!(f, "FAILED ({failure})"),
+            Self::Ok => write!(f, "Ok"),
+        }
+    }
+}
+
+/// Why a file failed verification
+///
+/// Custom `Verify` callbacks create this with `Failure::new` and add as much
+/// detail as they have.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    /// What went wrong.
+    pub reason: String,
+    /// The algorithm the file was verified with, e.g. `sha256` or `minisign`.
+    pub algorithm: Option<String>,
+    /// The expected value, e.g. a hash.
+    pub expected: Option<String>,
+    /// The value found for the file.
+    pub actual: Option<String>,
+    /// The error that prevented verifying the file.
+    pub cause: Option<std::sync::Arc<dyn std::error::Error + Send + Sync>>,
+}
+
+impl Failure {
+    /// A failure for `reason`.
+    #[must_use]
+    pub fn new(reason: &str) -> Self {
+        Self {
+            reason: reason.to_owned(),
+            algorithm: None,
+            expected: None,
+            actual: None,
+            cause: None,
+        }
+    }
+
+    /// Set the `algorithm` the file was verified with.
+    #[must_use]
+    pub fn algorithm(mut self, algorithm: &str) -> Self {
+        self.algorithm = Some(algorithm.to_owned());
+        self
+    }
+
+    /// Set the `expected` value and the `actual` value found for the file.
+    #[must_use]
+    pub fn mismatch(mut self, expected: &str, actual: &str) -> Self {
+        self.expected = Some(expected.to_owned());
+        self.actual = Some(actual.to_owned());
+        self
+    }
+
+    /// Set the error that prevented verifying the file.
+    #[must_use]
+    pub fn cause<E: std::error::Error + Send + Sync + 'static>(mut self, cause: E) -> Self {
+        self.cause = Some(std::sync::Arc::new(cause));
+        self
+    }
+}
+
+impl PartialEq for Failure {
+    fn eq(&self, other: &Self) -> bool {
+        let cause = |f: &Self| f.cause.as_ref().map(ToString::to_string);
+        self.reason == other.reason
+            && self.algorithm == other.algorithm
+            && self.expected == other.expected
+            && self.actual == other.actual
+            && cause(self) == cause(other)
+    }
+}
+
+impl Eq for Failure {}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.reason)?;
+        if let Some(algorithm) = &self.algorithm {
+            write!(f, " [{algorithm}]")?;
+        }
+        if let (Some(expected), Some(actual)) = (&self.expected, &self.actual) {
+            write!(f, ": expected {expected}, got {actual}")?;
+        }
+        if let Some(cause) = &self.cause {
+            write!(f, ": {cause}")?;
+        }
+        Ok(())
     }
 }
 
@@ -49,9 +178,18 @@ impl std::fmt::Display for Verification {
 /// Do nothing to verify the download
 #[must_use]
 pub fn noop() -> crate::Verify {
-    std::sync::Arc::new(|_: std::path::PathBuf, _: &crate::SimpleProgress| {
-        Verification::NotVerified
+    static NOOP: std::sync::OnceLock<crate::Verify> = std::sync::OnceLock::new();
+    NOOP.get_or_init(|| {
+        std::sync::Arc::new(|_: std::path::PathBuf, _: &crate::SimpleProgress| {
+            Verification::NotVerified
+        })
     })
+    .clone()
+}
+
+/// Is `verify` the callback returned by `noop`?
+pub(crate) fn is_noop(verify: &crate::Verify) -> bool {
+    std::sync::Arc::ptr_eq(verify, &noop())
 }
 
 // ----------------------------------------------------------------------
@@ -62,42 +200,1254 @@ pub fn noop() -> crate::Verify {
 #[cfg(feature = "verify")]
 #[must_use]
 pub fn with_digest<D: digest::Digest>(hash: Vec<u8>) -> crate::Verify {
-    use std::io::Read;
+    digest_verify::<D>(hash, None)
+}
 
+#[cfg(feature = "verify")]
+fn digest_verify<D: digest::Digest>(
+    hash: Vec<u8>,
+    algorithm: Option<&'static str>,
+) -> crate::Verify {
     std::sync::Arc::new(
         move |path: std::path::PathBuf, cb: &crate::SimpleProgress| {
             let mut hasher = D::new();
+            match read_file(&path, None, cb, &mut |data| hasher.update(data)) {
+                Ok(()) => compare(&hasher.finalize(), &hash, algorithm),
+                Err(e) => unreadable(e),
+            }
+        },
+    )
+}
 
-            if let Ok(file) = std::fs::OpenOptions::new().read(true).open(&path) {
-                let mut reader = std::io::BufReader::with_capacity(1024 * 1024, file);
-                let mut current = 0;
+/// Compare the `actual` hash of a file to the `expected` one.
+#[cfg(feature = "verify")]
+fn compare(actual: &[u8], expected: &[u8], algorithm: Option<&str>) -> Verification {
+    if actual == expected {
+        return Verification::Ok;
+    }
+    let failure =
+        Failure::new("Hash mismatch").mismatch(&hex::encode(expected), &hex::encode(actual));
+    Verification::Failed(match algorithm {
+        Some(algorithm) => failure.algorithm(algorithm),
+        None => failure,
+    })
+}
 
-                let mut buffer = [0_u8; 1024 * 1024];
-                while let Ok(n) = reader.read(&mut buffer[..]) {
-                    if n == 0 {
-                        break;
-                    }
+/// A `Streaming` verifier comparing a digest of the data to a hash
+#[cfg(feature = "verify")]
+struct DigestVerifier<D> {
+    hasher: D,
+    hash: std::sync::Arc<[u8]>,
+    algorithm: Option<&'static str>,
+}
 
-                    hasher.update(&buffer[..n]);
+#[cfg(feature = "verify")]
+impl<D: digest::Digest + Send> Streaming for DigestVerifier<D> {
+    fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
 
-                    cb(current);
-                    current += n as u64;
-                }
+    fn finish(self: Box<Self>) -> Verification {
+        compare(&self.hasher.finalize(), &self.hash, self.algorithm)
+    }
+}
 
-                let result = hasher.finalize();
+/// Make sure the downloaded data matches a provided hash using a provided
+/// Digest function, hashing the data while it is received
+#[cfg(feature = "verify")]
+#[must_use]
+pub fn streaming_digest<D: digest::Digest + Send + 'static>(hash: Vec<u8>) -> StreamingVerify {
+    digest_streaming::<D>(hash, None)
+}
+
+#[cfg(feature = "verify")]
+fn digest_streaming<D: digest::Digest + Send + 'static>(
+    hash: Vec<u8>,
+    algorithm: Option<&'static str>,
+) -> StreamingVerify {
+    let hash: std::sync::Arc<[u8]> = hash.into();
+    std::sync::Arc::new(move || {
+        Box::new(DigestVerifier {
+            hasher: D::new(),
+            hash: hash.clone(),
+            algorithm,
+        })
+    })
+}
+
+// ----------------------------------------------------------------------
+// - Checksum:
+// ----------------------------------------------------------------------
+
+/// A hash algorithm supported by `Checksum`
+#[cfg(feature = "verify")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Algorithm {
+    /// MD5
+    Md5,
+    /// SHA-1
+    Sha1,
+    /// SHA-224
+    Sha224,
+    /// SHA-256
+    Sha256,
+    /// SHA-384
+    Sha384,
+    /// SHA-512
+    Sha512,
+    /// SHA3-224
+    Sha3_224,
+    /// SHA3-256
+    Sha3_256,
+    /// SHA3-384
+    Sha3_384,
+    /// SHA3-512
+    Sha3_512,
+    /// `BLAKE2b` with 512 bits of output
+    Blake2b,
+    /// `BLAKE2s` with 256 bits of output
+    Blake2s,
+    /// BLAKE3 with 256 bits of output
+    Blake3,
+}
+
+#[cfg(feature = "verify")]
+impl Algorithm {
+    const ALL: [Self; 13] = [
+        Self::Md5,
+        Self::Sha1,
+        Self::Sha224,
+        Self::Sha256,
+        Self::Sha384,
+        Self::Sha512,
+        Self::Sha3_224,
+        Self::Sha3_256,
+        Self::Sha3_384,
+        Self::Sha3_512,
+        Self::Blake2b,
+        Self::Blake2s,
+        Self::Blake3,
+    ];
+
+    /// The algorithm called `name`, ignoring case, `-` and `_`
+    ///
+    /// `sha256`, `SHA-256` and `sha_256` all name the same algorithm.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalize = |n: &str| {
+            n.chars()
+                .filter(|c| *c != '-' && *c != '_')
+                .collect::<String>()
+                .to_ascii_lowercase()
+        };
+        let name = match normalize(name).as_str() {
+            "blake2b512" => "blake2b".to_owned(),
+            "blake2s256" => "blake2s".to_owned(),
+            n => n.to_owned(),
+        };
+        Self::ALL
+            .iter()
+            .find(|a| normalize(a.name()) == name)
+            .copied()
+    }
+
+    /// The algorithm producing digests of `length` bytes, `None` if there
+    /// is none. SHA-2 is preferred over other algorithms of the same length.
+    #[must_use]
+    pub const fn from_digest_length(length: usize) -> Option<Self> {
+        match length {
+            16 => Some(Self::Md5),
+            20 => Some(Self::Sha1),
+            28 => Some(Self::Sha224),
+            32 => Some(Self::Sha256),
+            48 => Some(Self::Sha384),
+            64 => Some(Self::Sha512),
+            _ => None,
+        }
+    }
 
-                if result.len() != hash.len() {
-                    return Verification::Failed;
+    /// The canonical name of the algorithm.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha224 => "sha224",
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+            Self::Sha512 => "sha512",
+            Self::Sha3_224 => "sha3-224",
+            Self::Sha3_256 => "sha3-256",
+            Self::Sha3_384 => "sha3-384",
+            Self::Sha3_512 => "sha3-512",
+            Self::Blake2b => "blake2b",
+            Self::Blake2s => "blake2s",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// The length of the digests in bytes.
+    #[must_use]
+    pub const fn digest_length(self) -> usize {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha224 | Self::Sha3_224 => 28,
+            Self::Sha256 | Self::Sha3_256 | Self::Blake2s | Self::Blake3 => 32,
+            Self::Sha384 | Self::Sha3_384 => 48,
+            Self::Sha512 | Self::Sha3_512 | Self::Blake2b => 64,
+        }
+    }
+}
+
+#[cfg(feature = "verify")]
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// `blake3::Hasher` as a `digest::Digest`
+#[cfg(feature = "verify")]
+#[derive(Clone, Default)]
+struct Blake3(blake3::Hasher);
+
+#[cfg(feature = "verify")]
+impl digest::HashMarker for Blake3 {}
+
+#[cfg(feature = "verify")]
+impl digest::OutputSizeUser for Blake3 {
+    type OutputSize = digest::consts::U32;
+}
+
+#[cfg(feature = "verify")]
+impl digest::Update for Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+}
+
+#[cfg(feature = "verify")]
+impl digest::FixedOutput for Blake3 {
+    fn finalize_into(self, out: &mut digest::Output<Self>) {
+        out.copy_from_slice(self.0.finalize().as_bytes());
+    }
+}
+
+/// Call `$function` with the digest type of `$algorithm` and the `$argument`s.
+#[cfg(feature = "verify")]
+macro_rules! with_algorithm {
+    ($algorithm:expr, $function:ident, $($argument:expr),*) => {
+        match $algorithm {
+            Algorithm::Md5 => $function::<md5::Md5>($($argument),*),
+            Algorithm::Sha1 => $function::<sha1::Sha1>($($argument),*),
+            Algorithm::Sha224 => $function::<sha2::Sha224>($($argument),*),
+            Algorithm::Sha256 => $function::<sha2::Sha256>($($argument),*),
+            Algorithm::Sha384 => $function::<sha2::Sha384>($($argument),*),
+            Algorithm::Sha512 => $function::<sha2::Sha512>($($argument),*),
+            Algorithm::Sha3_224 => $function::<sha3::Sha3_224>($($argument),*),
+            Algorithm::Sha3_256 => $function::<sha3::Sha3_256>($($argument),*),
+            Algorithm::Sha3_384 => $function::<sha3::Sha3_384>($($argument),*),
+            Algorithm::Sha3_512 => $function::<sha3::Sha3_512>($($argument),*),
+            Algorithm::Blake2b => $function::<blake2::Blake2b512>($($argument),*),
+            Algorithm::Blake2s => $function::<blake2::Blake2s256>($($argument),*),
+            Algorithm::Blake3 => $function::<Blake3>($($argument),*),
+        }
+    };
+}
+
+/// The hash a download is expected to have
+///
+/// Checksums are parsed from strings like
+///  * `sha256:<hex or base64>`, naming the algorithm,
+///  * `sha384-<base64>`, as used for Subresource Integrity, where several
+///    whitespace separated hashes may be given and the strongest one with a
+///    known algorithm is used, or
+///  * `<hex or base64>`, picking the algorithm by the length of the hash.
+///    32 byte hashes are taken to be SHA-256 and 64 byte hashes SHA-512.
+#[cfg(feature = "verify")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Checksum {
+    /// The algorithm to hash the download with.
+    pub algorithm: Algorithm,
+    /// The expected hash.
+    pub hash: Vec<u8>,
+}
+
+#[cfg(feature = "verify")]
+impl Checksum {
+    /// Expect `hash`, which is either hex or base64 encoded, using `algorithm`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if `hash` can not be decoded or
+    /// has the wrong length for `algorithm`.
+    pub fn new(algorithm: Algorithm, hash: &str) -> crate::Result<Self> {
+        Self::decoded(algorithm, hash.trim(), hash)
+    }
+
+    /// Decode `hash` for `algorithm`, reporting errors for `checksum`.
+    fn decoded(algorithm: Algorithm, hash: &str, checksum: &str) -> crate::Result<Self> {
+        let hash = decode(hash)
+            .filter(|h| h.len() == algorithm.digest_length())
+            .ok_or_else(|| {
+                invalid(
+                    checksum,
+                    &format!(
+                        "expected {} bytes of hex or base64 for {algorithm}",
+                        algorithm.digest_length()
+                    ),
+                )
+            })?;
+        Ok(Self { algorithm, hash })
+    }
+
+    /// Parse a `checksum` in any of the supported formats.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the algorithm is unknown or
+    /// the hash can not be decoded or has the wrong length.
+    pub fn parse(checksum: &str) -> crate::Result<Self> {
+        let checksum = checksum.trim();
+        if checksum.contains(char::is_whitespace) {
+            // Subresource Integrity with several hashes, unknown algorithms are ignored:
+            let (algorithm, hash) = checksum
+                .split_whitespace()
+                .filter_map(integrity)
+                .filter_map(|(name, hash)| Some((Algorithm::from_name(name)?, hash)))
+                .rev()
+                .max_by_key(|(algorithm, _)| algorithm.digest_length())
+                .ok_or_else(|| invalid(checksum, "no hash with a known algorithm"))?;
+            return Self::decoded(algorithm, hash, checksum);
+        }
+        if let Some((name, hash)) = checksum.split_once(':').or_else(|| integrity(checksum)) {
+            let algorithm = Algorithm::from_name(name)
+                .ok_or_else(|| invalid(checksum, &format!("unknown algorithm \"{name}\"")))?;
+            return Self::decoded(algorithm, hash, checksum);
+        }
+        let hash = decode(checksum).ok_or_else(|| invalid(checksum, "not hex or base64"))?;
+        let algorithm = Algorithm::from_digest_length(hash.len()).ok_or_else(|| {
+            invalid(
+                checksum,
+                &format!("no known algorithm has a {} byte hash", hash.len()),
+            )
+        })?;
+        Ok(Self { algorithm, hash })
+    }
+
+    /// A `Verify` callback reading the downloaded file to check it.
+    #[must_use]
+    pub fn verify(&self) -> crate::Verify {
+        with_algorithm!(
+            self.algorithm,
+            digest_verify,
+            self.hash.clone(),
+            Some(self.algorithm.name())
+        )
+    }
+
+    /// A `StreamingVerify` checking the download while it is received.
+    #[must_use]
+    pub fn streaming(&self) -> StreamingVerify {
+        with_algorithm!(
+            self.algorithm,
+            digest_streaming,
+            self.hash.clone(),
+            Some(self.algorithm.name())
+        )
+    }
+}
+
+#[cfg(feature = "verify")]
+impl std::str::FromStr for Checksum {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(feature = "verify")]
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm, hex::encode(&self.hash))
+    }
+}
+
+/// Split a Subresource Integrity `token` into the name of the algorithm and
+/// the hash, dropping options after a `?`.
+#[cfg(feature = "verify")]
+fn integrity(token: &str) -> Option<(&str, &str)> {
+    let (name, hash) = token.rsplit_once('-')?;
+    Some((name, hash.split_once('?').map_or(hash, |(h, _)| h)))
+}
+
+/// Decode a hash given as hex or base64.
+#[cfg(feature = "verify")]
+fn decode(hash: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+
+    if hash.is_empty() {
+        return None;
+    }
+    hex::decode(hash)
+        .ok()
+        .or_else(|| base64::engine::general_purpose::STANDARD.decode(hash).ok())
+        .or_else(|| {
+            base64::engine::general_purpose::STANDARD_NO_PAD
+                .decode(hash)
+                .ok()
+        })
+}
+
+#[cfg(feature = "verify")]
+fn invalid(checksum: &str, reason: &str) -> crate::Error {
+    crate::Error::DownloadDefinition(format!("Invalid checksum \"{checksum}\": {reason}."))
+}
+
+// ----------------------------------------------------------------------
+// - Manifest:
+// ----------------------------------------------------------------------
+
+/// The checksums listed in a manifest like `SHA256SUMS` or `file.tar.gz.sha256`
+///
+/// Manifests in the format of GNU coreutils (`<hash>  <name>`, as written by
+/// `sha256sum`) and BSD (`SHA256 (<name>) = <hash>`, as written by `sha256` or
+/// `sha256sum --tag`) are supported, as are sidecar files holding nothing
+/// but a hash. PGP clear signed manifests are read without checking their
+/// signature.
+#[cfg(feature = "verify")]
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    /// The checksums by file name, as listed in the manifest.
+    pub entries: std::collections::HashMap<String, Checksum>,
+    /// A checksum listed without file name, which applies to any file.
+    pub unnamed: Option<Checksum>,
+}
+
+#[cfg(feature = "verify")]
+impl Manifest {
+    /// Parse the `content` of a manifest.
+    ///
+    /// Hashes without algorithm are taken to be `algorithm`, or guessed from
+    /// their length if that is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if a line is malformed.
+    pub fn parse(content: &str, algorithm: Option<Algorithm>) -> crate::Result<Self> {
+        let mut result = Self::default();
+        for (number, line) in manifest_lines(content) {
+            if line.trim_start().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = |e: crate::Error| {
+                let reason = match e {
+                    crate::Error::DownloadDefinition(reason) => reason,
+                    e => e.to_string(),
+                };
+                crate::Error::DownloadDefinition(format!(
+                    "Checksum manifest line {}: {reason}",
+                    number + 1
+                ))
+            };
+            let (name, checksum) = parse_manifest_line(line, algorithm).map_err(malformed)?;
+            match name {
+                Some(name) => {
+                    result.entries.insert(name, checksum);
                 }
-                for i in 0..result.len() {
-                    if result[i] != hash[i] {
-                        return Verification::Failed;
+                None => result.unnamed = Some(checksum),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Load the manifest at `path`.
+    ///
+    /// The algorithm of hashes without one is guessed from the file name of the
+    /// manifest (e.g. `SHA256SUMS`, `MD5SUMS` or `file.sha512`) or the length of
+    /// the hash.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the manifest can not be read
+    /// or is malformed.
+    pub fn load(path: &std::path::Path) -> crate::Result<Self> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            crate::Error::DownloadDefinition(format!(
+                "Failed to read checksum manifest \"{}\": {e}",
+                path.to_string_lossy()
+            ))
+        })?;
+        Self::parse(&content, manifest_algorithm(path))
+    }
+
+    /// Download the manifest at `url` using the `downloader`.
+    ///
+    /// The algorithm is guessed as in `load`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the manifest can not be downloaded or is malformed.
+    pub fn download(downloader: &mut crate::Downloader, url: &str) -> crate::Result<Self> {
+        let results = downloader.download(&[memory_download(url)])?;
+        Self::from_summary(&received(url, results)?)
+    }
+
+    /// Download the manifest at `url` using the `downloader` asyncroniously.
+    ///
+    /// The algorithm is guessed as in `load`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the manifest can not be downloaded or is malformed.
+    pub async fn async_download(
+        downloader: &mut crate::Downloader,
+        url: &str,
+    ) -> crate::Result<Self> {
+        let results = downloader.async_download(&[memory_download(url)]).await?;
+        Self::from_summary(&received(url, results)?)
+    }
+
+    /// Load the manifest at `path` if it was signed with `signature` by any
+    /// of the trusted `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the manifest can not be read,
+    /// is malformed or the signature does not match.
+    #[cfg(feature = "signature")]
+    pub fn load_signed(
+        path: &std::path::Path,
+        signature: &Signature,
+        keys: &[PublicKey],
+    ) -> crate::Result<Self> {
+        let verification = signature.check(path, keys, &|_| {});
+        if !verification.is_ok() {
+            return Err(crate::Error::DownloadDefinition(format!(
+                "The signature of checksum manifest \"{}\" is not valid: {verification}.",
+                path.to_string_lossy()
+            )));
+        }
+        Self::load(path)
+    }
+
+    /// Download the manifest at `url` using the `downloader` and make sure it
+    /// was signed with `signature` by any of the trusted `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Verification` if the signature does not match, and
+    /// as `download` does otherwise.
+    #[cfg(feature = "signature")]
+    pub fn download_signed(
+        downloader: &mut crate::Downloader,
+        url: &str,
+        signature: &Signature,
+        keys: &[PublicKey],
+    ) -> crate::Result<Self> {
+        let download = memory_download(url).verify(signature.verify(keys));
+        let results = downloader.download(&[download])?;
+        Self::from_summary(&received(url, results)?)
+    }
+
+    /// Download the manifest at `url` using the `downloader` asyncroniously
+    /// and make sure it was signed with `signature` by any of the trusted `keys`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Verification` if the signature does not match, and
+    /// as `download` does otherwise.
+    #[cfg(feature = "signature")]
+    pub async fn async_download_signed(
+        downloader: &mut crate::Downloader,
+        url: &str,
+        signature: &Signature,
+        keys: &[PublicKey],
+    ) -> crate::Result<Self> {
+        let download = memory_download(url).verify(signature.verify(keys));
+        let results = downloader.async_download(&[download]).await?;
+        Self::from_summary(&received(url, results)?)
+    }
+
+    fn from_summary(summary: &crate::DownloadSummary) -> crate::Result<Self> {
+        let content = String::from_utf8_lossy(summary.data.as_deref().unwrap_or_default());
+        Self::parse(&content, manifest_algorithm(&summary.file_name))
+    }
+
+    /// The checksum for the file called `file_name`
+    ///
+    /// Entries listed with a path match by their last component, if that is
+    /// unique in the manifest.
+    #[must_use]
+    pub fn checksum(&self, file_name: &str) -> Option<&Checksum> {
+        if let Some(checksum) = self.entries.get(file_name) {
+            return Some(checksum);
+        }
+        let mut matching = self
+            .entries
+            .iter()
+            .filter(|(name, _)| name.rsplit(['/', '\\']).next() == Some(file_name));
+        match (matching.next(), matching.next()) {
+            (Some((_, checksum)), None) => Some(checksum),
+            _ => self.unnamed.as_ref(),
+        }
+    }
+
+    /// Verify `download` against the checksum listed for its file name
+    ///
+    /// The `download` fails verification if there is no such checksum. Set the
+    /// `file_name` of the `download` before attaching a manifest to it.
+    #[must_use]
+    pub fn attach(&self, download: crate::Download) -> crate::Download {
+        let file_name = download
+            .file_name
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(checksum) = self.checksum(&file_name) {
+            return download.verify_streaming(checksum.streaming());
+        }
+        let reason = format!("No checksum for \"{file_name}\" in the manifest");
+        download.verify(std::sync::Arc::new(
+            move |_: std::path::PathBuf, _: &crate::SimpleProgress| Verification::failed(&reason),
+        ))
+    }
+}
+
+/// A `Download` fetching the small file at `url` into memory.
+#[cfg(feature = "verify")]
+fn memory_download(url: &str) -> crate::Download {
+    crate::Download::new(url).sink(crate::download::Sink::Memory)
+}
+
+/// The summary of the only download in `results`.
+#[cfg(feature = "verify")]
+fn received(
+    url: &str,
+    results: Vec<crate::Result<crate::DownloadSummary>>,
+) -> crate::Result<crate::DownloadSummary> {
+    results
+        .into_iter()
+        .next()
+        .ok_or_else(|| crate::Error::DownloadDefinition(format!("Nothing received from {url}.")))?
+}
+
+/// Guess the algorithm of the manifest at `path` from its name.
+#[cfg(feature = "verify")]
+fn manifest_algorithm(path: &std::path::Path) -> Option<Algorithm> {
+    let from_name = |name: &str| match name.to_ascii_lowercase().as_str() {
+        "b2" => Some(Algorithm::Blake2b),
+        "b3" => Some(Algorithm::Blake3),
+        n => Algorithm::from_name(n),
+    };
+    let extension = path.extension().map(|e| e.to_string_lossy());
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_ascii_lowercase());
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase());
+    extension.and_then(|e| from_name(&e)).or_else(|| {
+        // `SHA256SUMS`, `sha256sum.txt`, ...
+        [name, stem].iter().flatten().find_map(|n| {
+            n.strip_suffix("sums")
+                .or_else(|| n.strip_suffix("sum"))
+                .and_then(from_name)
+        })
+    })
+}
+
+/// The lines of a manifest `content` with their index, leaving out the armor
+/// of PGP clear signed manifests and undoing their dash-escaping.
+#[cfg(feature = "verify")]
+fn manifest_lines(content: &str) -> Vec<(usize, &str)> {
+    enum Armor {
+        Outside,
+        Header,
+        Signed,
+        Signature,
+    }
+
+    let mut armor = Armor::Outside;
+    let mut result = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim_end();
+        match armor {
+            Armor::Outside if line == "-----BEGIN PGP SIGNED MESSAGE-----" => armor = Armor::Header,
+            Armor::Outside => result.push((number, line)),
+            // `Hash:` lines up to the first empty one:
+            Armor::Header if line.is_empty() => armor = Armor::Signed,
+            Armor::Signed if line == "-----BEGIN PGP SIGNATURE-----" => armor = Armor::Signature,
+            Armor::Signed => result.push((number, line.strip_prefix("- ").unwrap_or(line))),
+            Armor::Signature if line == "-----END PGP SIGNATURE-----" => armor = Armor::Outside,
+            Armor::Header | Armor::Signature => {}
+        }
+    }
+    result
+}
+
+/// Undo the escaping of `\\` and newlines in file names of manifests.
+#[cfg(feature = "verify")]
+fn unescape(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some(other) => result.push(other),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+/// Parse one `line` of a manifest into the file name (if any) and checksum.
+#[cfg(feature = "verify")]
+fn parse_manifest_line(
+    line: &str,
+    algorithm: Option<Algorithm>,
+) -> crate::Result<(Option<String>, Checksum)> {
+    let checksum =
+        |hash: &str| algorithm.map_or_else(|| Checksum::parse(hash), |a| Checksum::new(a, hash));
+
+    // Lines with file names containing `\` or line breaks start with a `\`:
+    let (escaped, line) = line.strip_prefix('\\').map_or((false, line), |l| (true, l));
+    let file_name = |name: &str| {
+        if escaped {
+            unescape(name)
+        } else {
+            name.to_owned()
+        }
+    };
+
+    // BSD: `SHA256 (<name>) = <hash>`
+    if let Some((name, hash)) = line
+        .split_once(" (")
+        .and_then(|(a, rest)| Some((a, rest.rsplit_once(") = ")?)))
+        .and_then(|(a, (name, hash))| Some((Algorithm::from_name(a)?, name, hash)))
+        .map(|(a, name, hash)| (name, Checksum::new(a, hash)))
+    {
+        return Ok((Some(file_name(name)), hash?));
+    }
+
+    // GNU: `<hash>  <name>` or `<hash> *<name>`
+    let Some((hash, name)) = line.split_once(' ') else {
+        return Ok((None, checksum(line.trim())?));
+    };
+    Ok((
+        Some(file_name(name.strip_prefix([' ', '*']).unwrap_or(name))),
+        checksum(hash)?,
+    ))
+}
+
+// ----------------------------------------------------------------------
+// - Signature:
+// ----------------------------------------------------------------------
+
+/// A public key trusted to sign downloads
+#[cfg(feature = "signature")]
+#[derive(Clone, Debug)]
+pub enum PublicKey {
+    /// A minisign public key.
+    Minisign(minisign_verify::PublicKey),
+    /// A raw Ed25519 public key.
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+#[cfg(feature = "signature")]
+impl PublicKey {
+    /// Parse a minisign public `key`, either the base64 encoded key alone or
+    /// the contents of a `.pub` file.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the `key` is malformed.
+    pub fn minisign(key: &str) -> crate::Result<Self> {
+        let key = key.trim();
+        let parsed = if key.contains('\n') {
+            minisign_verify::PublicKey::decode(key)
+        } else {
+            minisign_verify::PublicKey::from_base64(key)
+        };
+        parsed
+            .map(Self::Minisign)
+            .map_err(|e| invalid_key(key, &e.to_string()))
+    }
+
+    /// Parse a raw Ed25519 public `key`, given as hex or base64.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the `key` is malformed.
+    pub fn ed25519(key: &str) -> crate::Result<Self> {
+        use std::convert::TryInto;
+
+        let key = key.trim();
+        let bytes: [u8; 32] = decode(key)
+            .and_then(|k| k.try_into().ok())
+            .ok_or_else(|| invalid_key(key, "expected 32 bytes of hex or base64"))?;
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+            .map(Self::Ed25519)
+            .map_err(|e| invalid_key(key, &e.to_string()))
+    }
+}
+
+/// A detached signature of a download
+#[cfg(feature = "signature")]
+#[derive(Clone)]
+pub enum Signature {
+    /// A minisign signature, as found in `.minisig` files.
+    Minisign(minisign_verify::Signature),
+    /// A raw Ed25519 signature of the complete file.
+    Ed25519(ed25519_dalek::Signature),
+}
+
+#[cfg(feature = "signature")]
+impl Signature {
+    /// Parse a signature: The contents of a `.minisig` file, or an Ed25519
+    /// signature given as 64 raw bytes, hex or base64.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the signature is malformed.
+    pub fn parse(data: &[u8]) -> crate::Result<Self> {
+        use std::convert::TryInto;
+
+        let text = std::str::from_utf8(data).map(str::trim).unwrap_or_default();
+        if text.starts_with("untrusted comment:") {
+            return minisign_verify::Signature::decode(text)
+                .map(Self::Minisign)
+                .map_err(|e| invalid_signature(&e.to_string()));
+        }
+        let bytes: [u8; 64] = data
+            .try_into()
+            .ok()
+            .or_else(|| decode(text).and_then(|s| s.try_into().ok()))
+            .ok_or_else(|| {
+                invalid_signature("not minisign or 64 bytes of raw, hex or base64 Ed25519")
+            })?;
+        Ok(Self::Ed25519(ed25519_dalek::Signature::from_bytes(&bytes)))
+    }
+
+    /// Load the signature stored at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::DownloadDefinition` if the signature can not be read
+    /// or is malformed.
+    pub fn load(path: &std::path::Path) -> crate::Result<Self> {
+        let data = std::fs::read(path).map_err(|e| {
+            crate::Error::DownloadDefinition(format!(
+                "Failed to read signature \"{}\": {e}",
+                path.to_string_lossy()
+            ))
+        })?;
+        Self::parse(&data)
+    }
+
+    /// Download the signature at `url` using the `downloader`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the signature can not be downloaded or is malformed.
+    pub fn download(downloader: &mut crate::Downloader, url: &str) -> crate::Result<Self> {
+        let results = downloader.download(&[memory_download(url)])?;
+        Self::parse(received(url, results)?.data.as_deref().unwrap_or_default())
+    }
+
+    /// Download the signature at `url` using the `downloader` asyncroniously.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the signature can not be downloaded or is malformed.
+    pub async fn async_download(
+        downloader: &mut crate::Downloader,
+        url: &str,
+    ) -> crate::Result<Self> {
+        let results = downloader.async_download(&[memory_download(url)]).await?;
+        Self::parse(received(url, results)?.data.as_deref().unwrap_or_default())
+    }
+
+    /// A `Verify` callback checking that the downloaded file was signed with
+    /// this signature by any of the trusted `keys`
+    ///
+    /// Keys of a different kind than the signature are ignored. Minisign
+    /// signatures are checked while reading the file piece by piece. Raw
+    /// Ed25519 signatures and legacy minisign signatures (as made by
+    /// `minisign -l`) cover the complete file though, which is read into
+    /// memory to check them. Those only suit files that fit into memory.
+    #[must_use]
+    pub fn verify(&self, keys: &[PublicKey]) -> crate::Verify {
+        let signature = self.clone();
+        let keys = keys.to_vec();
+        std::sync::Arc::new(
+            move |path: std::path::PathBuf, progress: &crate::SimpleProgress| {
+                signature.check(&path, &keys, progress)
+            },
+        )
+    }
+
+    /// Check the file at `path` against this signature and the trusted `keys`.
+    fn check(
+        &self,
+        path: &std::path::Path,
+        keys: &[PublicKey],
+        progress: &crate::SimpleProgress,
+    ) -> Verification {
+        match self {
+            Self::Minisign(signature) => {
+                let mut error = None;
+                for key in keys.iter().filter_map(|k| match k {
+                    PublicKey::Minisign(key) => Some(key),
+                    PublicKey::Ed25519(_) => None,
+                }) {
+                    match key.verify_stream(signature) {
+                        Ok(verifier) => {
+                            let mut verifier = Box::new(Minisign(verifier));
+                            return match feed(&mut *verifier, path, None, progress) {
+                                Ok(()) => verifier.finish(),
+                                Err(e) => unreadable(e),
+                            };
+                        }
+                        Err(minisign_verify::Error::UnsupportedLegacyMode) => {
+                            return match read_whole(path, progress) {
+                                Ok(data) => minisign_result(key.verify(&data, signature, true)),
+                                Err(e) => unreadable(e),
+                            };
+                        }
+                        Err(e) => error = Some(e),
                     }
                 }
-                return Verification::Ok;
+                let failure =
+                    Failure::new("No trusted key made the signature").algorithm("minisign");
+                Verification::Failed(match error {
+                    Some(e) => failure.cause(e),
+                    None => failure,
+                })
+            }
+            Self::Ed25519(signature) => {
+                let data = match read_whole(path, progress) {
+                    Ok(data) => data,
+                    Err(e) => return unreadable(e),
+                };
+                if keys.iter().any(|k| match k {
+                    PublicKey::Ed25519(key) => key.verify_strict(&data, signature).is_ok(),
+                    PublicKey::Minisign(_) => false,
+                }) {
+                    Verification::Ok
+                } else {
+                    Verification::Failed(
+                        Failure::new("The signature does not match any trusted key")
+                            .algorithm("ed25519"),
+                    )
+                }
             }
+        }
+    }
+}
 
-            Verification::Failed
-        },
-    )
+/// Feeds a minisign `StreamVerifier`.
+#[cfg(feature = "signature")]
+struct Minisign<'a>(minisign_verify::StreamVerifier<'a>);
+
+#[cfg(feature = "signature")]
+impl Streaming for Minisign<'_> {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finish(mut self: Box<Self>) -> Verification {
+        minisign_result(self.0.finalize())
+    }
+}
+
+/// The `Verification` for the `result` of checking a minisign signature.
+#[cfg(feature = "signature")]
+fn minisign_result(result: Result<(), minisign_verify::Error>) -> Verification {
+    match result {
+        Ok(()) => Verification::Ok,
+        Err(e) => Verification::Failed(
+            Failure::new("The signature does not match")
+                .algorithm("minisign")
+                .cause(e),
+        ),
+    }
+}
+
+/// Read the complete file at `path`, for signatures that can not be checked
+/// piece by piece.
+#[cfg(feature = "signature")]
+fn read_whole(path: &std::path::Path, progress: &SimpleProgress) -> std::io::Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+    progress(data.len() as u64);
+    Ok(data)
+}
+
+#[cfg(feature = "signature")]
+fn invalid_key(key: &str, reason: &str) -> crate::Error {
+    crate::Error::DownloadDefinition(format!("Invalid public key \"{key}\": {reason}."))
+}
+
+#[cfg(feature = "signature")]
+fn invalid_signature(reason: &str) -> crate::Error {
+    crate::Error::DownloadDefinition(format!("Invalid signature: {reason}."))
+}
+
+// ----------------------------------------------------------------------
+// - Helper:
+// ----------------------------------------------------------------------
+
+/// Feed the part of the file at `path` up to `length` into `verifier`.
+///
+/// `progress` is called with the number of bytes fed so far.
+pub(crate) fn feed(
+    verifier: &mut dyn Streaming,
+    path: &std::path::Path,
+    length: Option<u64>,
+    progress: &SimpleProgress,
+) -> std::io::Result<()> {
+    read_file(path, length, progress, &mut |data| verifier.update(data))
+}
+
+/// Pass the part of the file at `path` up to `length` to `update` in chunks.
+///
+/// `progress` is called with the number of bytes read so far.
+fn read_file(
+    path: &std::path::Path,
+    length: Option<u64>,
+    progress: &SimpleProgress,
+    update: &mut dyn FnMut(&[u8]),
+) -> std::io::Result<()> {
+    use std::io::Read;
+
+    let mut reader = std::fs::File::open(path)?.take(length.unwrap_or(u64::MAX));
+    let mut buffer = vec![0_u8; 1024 * 1024];
+    let mut current = 0;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        update(&buffer[..n]);
+        current += n as u64;
+        progress(current);
+    }
+    Ok(())
+}
+
+/// The file to verify could not be read.
+pub(crate) fn unreadable(error: std::io::Error) -> Verification {
+    Verification::Failed(Failure::new("Failed to read the file").cause(error))
+}
+
+#[cfg(all(test, feature = "verify"))]
+mod tests {
+    use super::*;
+
+    const SHA256_HEX: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const SHA256_BASE64: &str = "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+    const SHA384_BASE64: &str = "OLBgp1GsljhM2TJ+sbHjaiH9txEUvgdDTAzHv2P24donTt6/529l+9Ua0vFImLlb";
+    const SHA512_BASE64: &str =
+        "z4PhNX7vuL3xVChQ1m2AB9Yg5AULVxXcg/SpIdNs6c5H0NE8XYXysP+DGNKHfuwvY7kxvUdBeoGlODJ6+SfaPg==";
+
+    fn sha256() -> Vec<u8> {
+        hex::decode(SHA256_HEX).unwrap()
+    }
+
+    #[test]
+    fn checksum_named_hex() {
+        let checksum = Checksum::parse(&format!("sha256:{SHA256_HEX}")).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        assert_eq!(checksum.hash, sha256());
+        let checksum =
+            Checksum::parse(&format!(" SHA-256:{} ", SHA256_HEX.to_uppercase())).unwrap();
+        assert_eq!(checksum.hash, sha256());
+    }
+
+    #[test]
+    fn checksum_named_base64() {
+        let checksum = Checksum::parse(&format!("sha256:{SHA256_BASE64}")).unwrap();
+        assert_eq!(checksum.hash, sha256());
+        let unpadded = SHA256_BASE64.trim_end_matches('=');
+        let checksum = Checksum::parse(&format!("sha256:{unpadded}")).unwrap();
+        assert_eq!(checksum.hash, sha256());
+    }
+
+    #[test]
+    fn checksum_bare_hash() {
+        let checksum = Checksum::parse(SHA256_HEX).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        let checksum = Checksum::parse(SHA512_BASE64).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha512);
+        let checksum = Checksum::parse("da39a3ee5e6b4b0d3255bfef95601890afd80709").unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha1);
+    }
+
+    #[test]
+    fn checksum_subresource_integrity() {
+        let checksum = Checksum::parse(&format!("sha384-{SHA384_BASE64}")).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha384);
+        assert_eq!(checksum.hash.len(), 48);
+        let checksum = Checksum::parse(&format!("sha256-{SHA256_BASE64}?ct=text/plain")).unwrap();
+        assert_eq!(checksum.hash, sha256());
+    }
+
+    #[test]
+    fn checksum_subresource_integrity_picks_strongest() {
+        let checksum = Checksum::parse(&format!(
+            "sha256-{SHA256_BASE64} sha512-{SHA512_BASE64}\tsha384-{SHA384_BASE64}"
+        ))
+        .unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha512);
+        let checksum = Checksum::parse(&format!("unknown-abc sha256-{SHA256_BASE64}")).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        assert!(Checksum::parse("unknown-abc other-def").is_err());
+        // The strongest hash has to be valid, no falling back to weaker ones:
+        assert!(Checksum::parse(&format!("sha256-{SHA256_BASE64} sha512-broken")).is_err());
+    }
+
+    #[test]
+    fn checksum_errors() {
+        assert!(Checksum::parse("").is_err());
+        assert!(Checksum::parse("foo:abcd").is_err());
+        assert!(Checksum::parse(&format!("sha512:{SHA256_HEX}")).is_err());
+        assert!(Checksum::parse("sha256:not a hash").is_err());
+        assert!(Checksum::parse("abcd").is_err());
+    }
+
+    #[test]
+    fn manifest_gnu() {
+        let manifest = Manifest::parse(
+            &format!("# checksums\n\n{SHA256_HEX}  file.txt\n{SHA256_HEX} *binary mode.bin\r\n"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert_eq!(manifest.checksum("file.txt").unwrap().hash, sha256());
+        assert!(manifest.checksum("binary mode.bin").is_some());
+        assert!(manifest.unnamed.is_none());
+    }
+
+    #[test]
+    fn manifest_bsd() {
+        let manifest = Manifest::parse(
+            &format!("SHA256 (file (1).txt) = {SHA256_HEX}\nSHA384 (other) = {SHA384_BASE64}\n"),
+            Some(Algorithm::Md5),
+        )
+        .unwrap();
+        let checksum = manifest.checksum("file (1).txt").unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        assert_eq!(
+            manifest.checksum("other").unwrap().algorithm,
+            Algorithm::Sha384
+        );
+    }
+
+    #[test]
+    fn manifest_escaped() {
+        let manifest = Manifest::parse(
+            &format!("\\{SHA256_HEX}  back\\\\slash\\nnewline\n\\SHA256 (a\\\\b) = {SHA256_HEX}\n"),
+            None,
+        )
+        .unwrap();
+        assert!(manifest.entries.contains_key("back\\slash\nnewline"));
+        assert!(manifest.entries.contains_key("a\\b"));
+    }
+
+    #[test]
+    fn manifest_unnamed() {
+        let manifest = Manifest::parse(&format!("{SHA256_HEX}\n"), None).unwrap();
+        assert!(manifest.entries.is_empty());
+        assert_eq!(manifest.unnamed.unwrap().hash, sha256());
+    }
+
+    #[test]
+    fn manifest_algorithm_hint() {
+        let manifest =
+            Manifest::parse(&format!("{SHA256_HEX}  file"), Some(Algorithm::Blake3)).unwrap();
+        assert_eq!(manifest.entries["file"].algorithm, Algorithm::Blake3);
+        assert!(Manifest::parse(&format!("{SHA256_HEX}  file"), Some(Algorithm::Sha512)).is_err());
+    }
+
+    #[test]
+    fn manifest_clear_signed() {
+        let content = format!(
+            "-----BEGIN PGP SIGNED MESSAGE-----\n\
+             Hash: SHA256\n\
+             \n\
+             {SHA256_HEX}  file.txt\n\
+             - {SHA256_HEX}  -dashed\n\
+             -----BEGIN PGP SIGNATURE-----\n\
+             \n\
+             iQIzBAEBCAAdFiEE0123456789abcdef\n\
+             =abcd\n\
+             -----END PGP SIGNATURE-----\n"
+        );
+        let manifest = Manifest::parse(&content, None).unwrap();
+        assert_eq!(manifest.entries.len(), 2);
+        assert!(manifest.entries.contains_key("file.txt"));
+        assert!(manifest.entries.contains_key("-dashed"));
+    }
+
+    #[test]
+    fn manifest_reports_malformed_line() {
+        let error = Manifest::parse(&format!("{SHA256_HEX}  a\nnonsense  b\n"), None).unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+    }
+
+    /// A minisign public key and a signature of `data` made with it, `legacy`
+    /// signing `data` itself instead of its hash.
+    #[cfg(feature = "signature")]
+    fn minisign(data: &[u8], legacy: bool) -> (PublicKey, Signature) {
+        use base64::Engine;
+        use blake2::Digest;
+        use ed25519_dalek::Signer;
+
+        let engine = base64::engine::general_purpose::STANDARD;
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let key_id = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        let public = [&b"Ed"[..], &key_id, key.verifying_key().as_bytes()].concat();
+        let (algorithm, signature) = if legacy {
+            (b"Ed", key.sign(data))
+        } else {
+            (b"ED", key.sign(&blake2::Blake2b512::digest(data)))
+        };
+        let trusted = "timestamp:0";
+        let global = key.sign(&[&signature.to_bytes()[..], trusted.as_bytes()].concat());
+        let signature = [&algorithm[..], &key_id, &signature.to_bytes()].concat();
+        let text = format!(
+            "untrusted comment: test\n{}\ntrusted comment: {trusted}\n{}\n",
+            engine.encode(signature),
+            engine.encode(global.to_bytes())
+        );
+        (
+            PublicKey::minisign(&engine.encode(public)).unwrap(),
+            Signature::parse(text.as_bytes()).unwrap(),
+        )
+    }
+
+    #[cfg(feature = "signature")]
+    #[test]
+    fn minisign_signatures() {
+        let path = std::env::temp_dir().join(format!(
+            "{}-minisign-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        std::fs::write(&path, b"signed data").unwrap();
+        for legacy in [false, true] {
+            let (key, signature) = minisign(b"signed data", legacy);
+            assert!(signature.verify(std::slice::from_ref(&key))(path.clone(), &|_| {}).is_ok());
+            let (_, other) = minisign(b"other data", legacy);
+            assert!(!other.verify(&[key])(path.clone(), &|_| {}).is_ok());
+        }
+        let _ = std::fs::remove_file(path);
+    }
 }